@@ -0,0 +1,276 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+use tracing::info;
+use crate::asset::ServerInfo;
+
+/// 登录后的会话, 在场景间通过 GameState 共享
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub account: String,
+    pub session_id: u32,
+    pub server: Option<ServerInfo>,
+    pub character: Option<CharacterInfo>,
+}
+
+/// 注册账号时填写的信息
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewAccount {
+    pub account: String,
+    pub password: String,
+    pub user_name: String,
+    pub question: String,
+    pub answer: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuthRequest {
+    Login { account: String, password: String },
+    NewAccount(NewAccount),
+    ChangePassword { account: String, password: String, new_password: String },
+}
+
+/// 认证结果, 失败时带原因
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuthResponse {
+    /// 成功时返回会话编号
+    Login(Result<u32, String>),
+    NewAccount(Result<(), String>),
+    ChangePassword(Result<(), String>),
+}
+
+/// 认证后端, 请求立即返回, 结果通过 poll 取回, 便于接入网络实现
+pub trait AuthBackend {
+    fn send(&mut self, request: AuthRequest);
+
+    fn poll(&mut self) -> Option<AuthResponse>;
+}
+
+/// 本地认证, 账号只保存在内存中, 用于开发时脱离服务器运行
+pub struct LocalAuth {
+    accounts: HashMap<String, NewAccount>,
+    responses: VecDeque<AuthResponse>,
+    next_session: u32,
+}
+
+impl LocalAuth {
+    /// 默认带一个 test/test 账号
+    pub fn new() -> Self {
+        let mut accounts = HashMap::new();
+        accounts.insert(String::from("test"), NewAccount {
+            account: String::from("test"),
+            password: String::from("test"),
+            user_name: String::from("test"),
+            question: String::new(),
+            answer: String::new(),
+        });
+        Self { accounts, responses: VecDeque::new(), next_session: 1 }
+    }
+}
+
+impl Default for LocalAuth {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AuthBackend for LocalAuth {
+    fn send(&mut self, request: AuthRequest) {
+        let response = match request {
+            AuthRequest::Login { account, password } => match self.accounts.get(&account) {
+                Some(a) if a.password == password => {
+                    self.next_session += 1;
+                    info!("本地登录: {}", account);
+                    AuthResponse::Login(Ok(self.next_session))
+                }
+                Some(_) => AuthResponse::Login(Err(String::from("密码错误"))),
+                None => AuthResponse::Login(Err(String::from("账号不存在"))),
+            },
+            AuthRequest::NewAccount(new) => {
+                if self.accounts.contains_key(&new.account) {
+                    AuthResponse::NewAccount(Err(String::from("账号已存在")))
+                } else {
+                    info!("本地注册: {}", new.account);
+                    self.accounts.insert(new.account.clone(), new);
+                    AuthResponse::NewAccount(Ok(()))
+                }
+            }
+            AuthRequest::ChangePassword { account, password, new_password } => match self.accounts.get_mut(&account) {
+                Some(a) if a.password == password => {
+                    a.password = new_password;
+                    AuthResponse::ChangePassword(Ok(()))
+                }
+                Some(_) => AuthResponse::ChangePassword(Err(String::from("原密码错误"))),
+                None => AuthResponse::ChangePassword(Err(String::from("账号不存在"))),
+            },
+        };
+        self.responses.push_back(response);
+    }
+
+    fn poll(&mut self) -> Option<AuthResponse> {
+        self.responses.pop_front()
+    }
+}
+
+/// 账号 3~10 位字母或数字
+pub fn validate_account(account: &str) -> Result<(), String> {
+    if account.len() < 3 || account.len() > 10 {
+        return Err(String::from("账号长度为 3~10 位"));
+    }
+    if !account.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(String::from("账号只能包含字母和数字"));
+    }
+    Ok(())
+}
+
+/// 密码 4~10 位, 不含空白
+pub fn validate_password(password: &str) -> Result<(), String> {
+    if password.len() < 4 || password.len() > 10 {
+        return Err(String::from("密码长度为 4~10 位"));
+    }
+    if password.chars().any(|c| c.is_whitespace() || !c.is_ascii()) {
+        return Err(String::from("密码不能包含空格或中文"));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Job {
+    Warrior,
+    Wizard,
+    Taoist,
+}
+
+impl Job {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Job::Warrior => "战士",
+            Job::Wizard => "法师",
+            Job::Taoist => "道士",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gender {
+    Male,
+    Female,
+}
+
+impl Gender {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Gender::Male => "男",
+            Gender::Female => "女",
+        }
+    }
+}
+
+/// 角色列表中的一个角色
+#[derive(Debug, Clone, PartialEq)]
+pub struct CharacterInfo {
+    pub name: String,
+    pub job: Job,
+    pub gender: Gender,
+    pub level: u16,
+    /// 衣服外观编号, 0 为无衣服
+    pub dress: u16,
+    /// 上次所在的地图编号和格子, 新角色为空, 从起始地图的出生点进入
+    pub location: Option<(String, i32, i32)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoleRequest {
+    List,
+    Create { name: String, job: Job, gender: Gender },
+    Delete { name: String },
+    Start { name: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoleResponse {
+    List(Result<Vec<CharacterInfo>, String>),
+    Create(Result<CharacterInfo, String>),
+    /// 成功时返回被删除的角色名
+    Delete(Result<String, String>),
+    /// 成功时返回进入游戏的角色名
+    Start(Result<String, String>),
+}
+
+/// 角色服务, 与 AuthBackend 一样请求立即返回, 结果通过 poll 取回
+pub trait AccountService {
+    fn send(&mut self, session: &Session, request: RoleRequest);
+
+    fn poll(&mut self) -> Option<RoleResponse>;
+}
+
+/// 本地角色服务, 角色只保存在内存中, 克隆的实例共享角色数据
+#[derive(Clone)]
+pub struct MockAccountService {
+    characters: Rc<RefCell<HashMap<String, Vec<CharacterInfo>>>>,
+    max_characters: usize,
+    responses: VecDeque<RoleResponse>,
+}
+
+impl MockAccountService {
+    pub fn new(max_characters: usize) -> Self {
+        Self { characters: Rc::new(RefCell::new(HashMap::new())), max_characters, responses: VecDeque::new() }
+    }
+}
+
+impl AccountService for MockAccountService {
+    fn send(&mut self, session: &Session, request: RoleRequest) {
+        let mut characters = self.characters.borrow_mut();
+        let response = match request {
+            RoleRequest::List => RoleResponse::List(Ok(characters.get(&session.account).cloned().unwrap_or_default())),
+            RoleRequest::Create { name, job, gender } => {
+                let count = characters.get(&session.account).map_or(0, |c| c.len());
+                if count >= self.max_characters {
+                    RoleResponse::Create(Err(String::from("角色数量已满")))
+                } else if characters.values().flatten().any(|c| c.name == name) {
+                    RoleResponse::Create(Err(String::from("角色名已存在")))
+                } else {
+                    let info = CharacterInfo { name, job, gender, level: 1, dress: 0, location: None };
+                    info!("本地创建角色: {} {:?} {:?}", info.name, job, gender);
+                    characters.entry(session.account.clone()).or_default().push(info.clone());
+                    RoleResponse::Create(Ok(info))
+                }
+            }
+            RoleRequest::Delete { name } => {
+                let list = characters.entry(session.account.clone()).or_default();
+                match list.iter().position(|c| c.name == name) {
+                    Some(i) => {
+                        list.remove(i);
+                        RoleResponse::Delete(Ok(name))
+                    }
+                    None => RoleResponse::Delete(Err(String::from("角色不存在"))),
+                }
+            }
+            RoleRequest::Start { name } => {
+                let found = characters.get(&session.account).is_some_and(|l| l.iter().any(|c| c.name == name));
+                if found {
+                    RoleResponse::Start(Ok(name))
+                } else {
+                    RoleResponse::Start(Err(String::from("角色不存在")))
+                }
+            }
+        };
+        self.responses.push_back(response);
+    }
+
+    fn poll(&mut self) -> Option<RoleResponse> {
+        self.responses.pop_front()
+    }
+}
+
+/// 角色名宽度(中文算 2)为 4~14, 不含空白和符号
+pub fn validate_character_name(name: &str) -> Result<(), String> {
+    let width = name.chars().map(|c| if c.is_ascii() { 1 } else { 2 }).sum::<usize>();
+    if !(4..=14).contains(&width) {
+        return Err(String::from("角色名长度为 2~7 个汉字或 4~14 个字母"));
+    }
+    if name.chars().any(|c| c.is_whitespace() || c.is_ascii_punctuation() || c.is_control()) {
+        return Err(String::from("角色名不能包含空格或符号"));
+    }
+    Ok(())
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::fs::File;
 use std::io::{BufReader, Read, Seek, SeekFrom};
@@ -5,13 +6,58 @@ use std::path::Path;
 use bytes::{Buf, Bytes};
 use flate2::{FlushDecompress, Status};
 use tracing::{error, warn};
+use crate::draw::blend::SpriteBlend;
 
 
 
+#[derive(Default)]
 pub struct MapData {
     pub width: u32,
     pub height: u32,
-    pub tiles: Vec<Tile>
+    /// 文件头中的标题, 多为编辑器写入的版本信息
+    pub title: String,
+    pub tiles: Vec<Tile>,
+    pub doors: HashMap<u8, Vec<usize>>,
+}
+
+impl MapData {
+    pub fn new(width: u32, height: u32, tiles: Vec<Tile>) -> Self {
+        let mut doors: HashMap<u8, Vec<usize>> = HashMap::new();
+        tiles.iter().enumerate().filter(|(_, t)| t.door_index() > 0).for_each(|(i, t)| {
+            doors.entry(t.door_index()).or_default().push(i);
+        });
+        Self { width, height, title: String::new(), tiles, doors }
+    }
+
+    pub fn tile(&self, x: i32, y: i32) -> Option<&Tile> {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return None;
+        }
+        self.tiles.get((x * self.height as i32 + y) as usize)
+    }
+
+    pub fn can_walk(&self, x: i32, y: i32) -> bool {
+        self.tile(x, y).is_some_and(|t| t.can_walk())
+    }
+
+    /// 开关门, 同一门号的所有格子一起切换, 返回门是否存在
+    pub fn set_door_open(&mut self, door_idx: u8, open: bool) -> bool {
+        if let Some(cells) = self.doors.get(&(door_idx & 0x7F)) {
+            for i in cells {
+                let tile = &mut self.tiles[*i];
+                tile.door_offset = if open { tile.door_offset | 0x80 } else { tile.door_offset & 0x7F };
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn is_door_open(&self, door_idx: u8) -> bool {
+        self.doors.get(&(door_idx & 0x7F))
+            .and_then(|cells| cells.first())
+            .is_some_and(|i| self.tiles[*i].is_door_open())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -47,6 +93,35 @@ impl Tile {
 
         Tile { back, middle, objects, door_idx, door_offset, frame, tick, objects_idx, light, back_idx, middle_idx }
     }
+
+    pub fn door_index(&self) -> u8 {
+        self.door_idx & 0x7F
+    }
+
+    /// 门的阻挡格(0x80), 关门时不可通行
+    pub fn is_door_core(&self) -> bool {
+        self.door_idx & 0x80 > 0
+    }
+
+    pub fn is_door_open(&self) -> bool {
+        self.door_offset & 0x80 > 0
+    }
+
+    /// 开门时物件帧需要加上的偏移
+    pub fn door_frame_offset(&self) -> u16 {
+        if self.door_index() > 0 && self.is_door_open() {
+            (self.door_offset & 0x7F) as u16
+        } else {
+            0
+        }
+    }
+
+    pub fn can_walk(&self) -> bool {
+        if self.back & 0x8000 > 0 || self.objects & 0x8000 > 0 {
+            return false;
+        }
+        !self.is_door_core() || self.is_door_open()
+    }
 }
 
 #[derive(Default)]
@@ -103,6 +178,7 @@ pub fn read_map_file<P: AsRef<Path> + Debug>(path: P) -> Option<MapData> {
     let mut header = &header[..];
     let width = header.get_u16_le() as u32;
     let height = header.get_u16_le() as u32;
+    let title = decode_short_string(&header[..17]);
     let length = ((file_size as u32 - 52) / (width * height)) as usize;
     let mut body = Vec::with_capacity(file_size as usize -52);
     file.read_to_end(&mut body).unwrap();
@@ -114,7 +190,395 @@ pub fn read_map_file<P: AsRef<Path> + Debug>(path: P) -> Option<MapData> {
         let tile = Tile::from(&body[start..end]);
         tiles.push(tile);
     }
-    Some(MapData {width, height, tiles})
+    let mut data = MapData::new(width, height, tiles);
+    data.title = title;
+    Some(data)
+}
+
+/// Delphi 的 ShortString: 首字节为长度, 之后是 GBK 编码的内容
+fn decode_short_string(bytes: &[u8]) -> String {
+    let len = (bytes[0] as usize).min(bytes.len() - 1);
+    let (text, _, _) = encoding_rs::GBK.decode(&bytes[1..=len]);
+    text.trim_end_matches('\0').trim().to_string()
+}
+
+/// 地图光照模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LightMode {
+    #[default]
+    Day,
+    Dawn,
+    Night,
+    Dark,
+}
+
+/// MapInfo 中的一条地图定义
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapInfo {
+    /// 逻辑编号, 场景通过它切换地图
+    pub code: String,
+    pub title: String,
+    /// map 目录下的文件名(不含扩展名)
+    pub file: String,
+    pub data_id: u32,
+    pub data_number: u32,
+    pub minimap: Option<u32>,
+    pub light: LightMode,
+    pub music: Option<String>,
+    pub start: Option<(i32, i32)>,
+}
+
+impl MapInfo {
+    pub fn new(code: &str, title: &str) -> Self {
+        Self {
+            code: String::from(code),
+            title: String::from(title),
+            file: String::from(code),
+            data_id: 1,
+            data_number: 1,
+            minimap: None,
+            light: LightMode::Day,
+            music: None,
+            start: None,
+        }
+    }
+}
+
+/// 读取文本表格, 返回去掉空行和 ';' 注释后的 (行号, 内容), 文件不存在时为空
+fn read_table_lines<P: AsRef<Path> + Debug>(path: P, name: &str) -> Vec<(usize, String)> {
+    let data = match std::fs::read(&path) {
+        Ok(data) => data,
+        Err(_) => {
+            error!("未找到{}: {:?}", name, path);
+            return Vec::new();
+        }
+    };
+    String::from_utf8_lossy(&data).lines().enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with(';'))
+        .map(|(i, line)| (i, String::from(line)))
+        .collect()
+}
+
+/// 读取 MapInfo 风格的地图表, 每行一张地图, ';' 开头为注释:
+/// [3 盟重省] FILE(n3) DATA(2,3) MINIMAP(103) LIGHT(NIGHT) MUSIC(mc.wav) START(333,333)
+pub fn read_map_info<P: AsRef<Path> + Debug>(path: P) -> Vec<MapInfo> {
+    let mut result = Vec::new();
+    for (i, line) in read_table_lines(&path, "地图表") {
+        match parse_map_info(&line) {
+            Some(info) => result.push(info),
+            None => warn!("地图表格式错误: {:?} 第 {} 行: {}", path, i, line),
+        }
+    }
+    result
+}
+
+pub const DIRECTIONS: u32 = 8;
+
+/// 一个动作在图片库中的位置: 第一个方向从 start 开始, 每个方向 frames 帧后空 skip 张
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ActionFrames {
+    pub start: u32,
+    pub frames: u32,
+    pub skip: u32,
+    /// 每帧时长(秒)
+    pub frame_time: f32,
+    /// 方向数, 角色为 8, 魔法效果可以是 1, 8 或 16
+    pub directions: u32,
+}
+
+impl ActionFrames {
+    pub const fn new(start: u32, frames: u32, skip: u32, frame_time: f32) -> Self {
+        Self { start, frames, skip, frame_time, directions: DIRECTIONS }
+    }
+
+    pub const fn with_directions(self, directions: u32) -> Self {
+        Self { directions, ..self }
+    }
+
+    /// direction 为 0 ~ directions-1(正上开始顺时针)
+    pub fn index(&self, direction: u8, frame: u32) -> u32 {
+        self.start + direction as u32 % self.directions.max(1) * (self.frames + self.skip) + frame % self.frames.max(1)
+    }
+
+    /// 所有方向占用的图片数, 用于一次加载整个动作
+    pub fn count(&self) -> u32 {
+        self.directions.max(1) * (self.frames + self.skip)
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.frames as f32 * self.frame_time
+    }
+}
+
+/// 怪物和 NPC 的动作
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ActorAction {
+    Stand,
+    Walk,
+    Attack,
+    Struck,
+    Die,
+    /// 死亡后躺在地上的尸体
+    Death,
+}
+
+impl ActorAction {
+    fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "STAND" => Some(ActorAction::Stand),
+            "WALK" => Some(ActorAction::Walk),
+            "ATTACK" => Some(ActorAction::Attack),
+            "STRUCK" => Some(ActorAction::Struck),
+            "DIE" => Some(ActorAction::Die),
+            "DEATH" => Some(ActorAction::Death),
+            _ => None,
+        }
+    }
+}
+
+/// 附加图层: 图片库和该外观在库中的起始序号, 帧序号与身体相同
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActorLayerInfo {
+    pub file: String,
+    pub start: u32,
+}
+
+/// 怪物或 NPC 的外观定义
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActorInfo {
+    pub appearance: u32,
+    pub name: String,
+    /// 图片库名, 如 Mon1, Npc
+    pub file: String,
+    /// 该外观在图片库中的起始序号, 动作的 start 相对于它
+    pub start: u32,
+    pub actions: HashMap<ActorAction, ActionFrames>,
+    pub shadow: Option<ActorLayerInfo>,
+    pub effect: Option<ActorLayerInfo>,
+}
+
+impl ActorInfo {
+    pub fn new(appearance: u32, name: &str) -> Self {
+        Self {
+            appearance,
+            name: String::from(name),
+            file: String::new(),
+            start: 0,
+            actions: HashMap::new(),
+            shadow: None,
+            effect: None,
+        }
+    }
+
+    /// 没有定义的动作用站立代替
+    pub fn frames(&self, action: ActorAction) -> Option<ActionFrames> {
+        self.actions.get(&action).or_else(|| self.actions.get(&ActorAction::Stand)).copied()
+    }
+}
+
+/// 读取怪物或 NPC 外观表, 每行一个外观, ';' 开头为注释.
+/// [@名称] 定义一种帧布局, 外观用 RACE(名称) 引用后可以再覆盖个别动作:
+/// [@MA10] STAND(0,4,6,200) WALK(80,6,4,160) ATTACK(160,6,4,100) STRUCK(240,2,0,100) DIE(260,10,0,100) DEATH(340,1,9,100)
+/// [1 鸡] FILE(Mon1) START(0) RACE(MA10) SHADOW(Mon1Shadow,0) EFFECT(Mon1Effect,0)
+/// 动作参数为 (起始, 帧数, 间隔, 每帧毫秒)
+pub fn read_actor_info<P: AsRef<Path> + Debug>(path: P) -> Vec<ActorInfo> {
+    let mut races: HashMap<String, HashMap<ActorAction, ActionFrames>> = HashMap::new();
+    let mut result = Vec::new();
+    for (i, line) in read_table_lines(&path, "外观表") {
+        match parse_actor_info(&line, &races) {
+            Some(info) if info.name.starts_with('@') => {
+                races.insert(info.name[1..].to_uppercase(), info.actions);
+            }
+            Some(info) if info.file.is_empty() || info.actions.is_empty() => warn!("外观缺少图片库或动作: {:?} 第 {} 行: {}", path, i, line),
+            Some(info) => result.push(info),
+            None => warn!("外观表格式错误: {:?} 第 {} 行: {}", path, i, line),
+        }
+    }
+    result
+}
+
+/// 魔法效果的定义
+#[derive(Debug, Clone, PartialEq)]
+pub struct EffectInfo {
+    pub id: u32,
+    pub name: String,
+    /// 图片库名, 如 Magic, Magic2
+    pub file: String,
+    /// 每个方向的帧, 方向数为 1, 8 或 16
+    pub frames: ActionFrames,
+    /// 循环播放的时长(秒), 为空时播放一遍
+    pub life: Option<f32>,
+    /// 飞行速度(像素/秒), 0 为不飞行
+    pub speed: f32,
+    /// 飞到目标后播放的效果
+    pub hit: Option<u32>,
+    pub blend: SpriteBlend,
+}
+
+impl EffectInfo {
+    pub fn new(id: u32, name: &str) -> Self {
+        Self {
+            id,
+            name: String::from(name),
+            file: String::new(),
+            frames: ActionFrames::new(0, 1, 0, 0.1).with_directions(1),
+            life: None,
+            speed: 0.,
+            hit: None,
+            blend: SpriteBlend::Additive,
+        }
+    }
+
+    pub fn is_projectile(&self) -> bool {
+        self.speed > 0.
+    }
+}
+
+/// 读取魔法效果表, 每行一个效果, ';' 开头为注释:
+/// [1 火球术] FILE(Magic) FRAMES(1820,6,4,100) DIR(16) SPEED(400) HIT(2)
+/// [2 火球爆炸] FILE(Magic) FRAMES(1980,10,0,80)
+/// [3 魔法盾] FILE(Magic) FRAMES(3890,3,0,150) LIFE(3000)
+/// FRAMES 参数为 (起始, 帧数, 间隔, 每帧毫秒), LIFE 为毫秒, BLEND 为 ALPHA, ADD(默认) 或 MULTIPLY
+pub fn read_effect_info<P: AsRef<Path> + Debug>(path: P) -> Vec<EffectInfo> {
+    let mut result = Vec::new();
+    for (i, line) in read_table_lines(&path, "效果表") {
+        match parse_effect_info(&line) {
+            Some(info) if info.file.is_empty() => warn!("效果缺少图片库: {:?} 第 {} 行: {}", path, i, line),
+            Some(info) => result.push(info),
+            None => warn!("效果表格式错误: {:?} 第 {} 行: {}", path, i, line),
+        }
+    }
+    result
+}
+
+/// 影子表中的一段物件, 这些物件在地面上画影子
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectShadow {
+    /// 物件图片库编号, 1 为 Objects, 2 为 Objects2, 以此类推
+    pub file: u32,
+    /// 图片序号范围, 包含两端
+    pub start: u32,
+    pub end: u32,
+    /// 影子帧相对物件图片的序号偏移, 为空时用物件图片生成影子
+    pub offset: Option<u32>,
+}
+
+impl ObjectShadow {
+    pub fn contains(&self, file: u32, index: u32) -> bool {
+        self.file == file && (self.start..=self.end).contains(&index)
+    }
+}
+
+/// 读取物件影子表, 每行一段物件, ';' 开头为注释:
+/// [1 0-350]
+/// [2 1200-1260] SHADOW(100)
+/// 头部为 (图片库编号 起始-结束), SHADOW 为影子帧的序号偏移
+pub fn read_object_shadows<P: AsRef<Path> + Debug>(path: P) -> Vec<ObjectShadow> {
+    let mut result = Vec::new();
+    for (i, line) in read_table_lines(&path, "物件影子表") {
+        match parse_object_shadow(&line) {
+            Some(shadow) => result.push(shadow),
+            None => warn!("物件影子表格式错误: {:?} 第 {} 行: {}", path, i, line),
+        }
+    }
+    result
+}
+
+/// 服务器列表中的一项
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerInfo {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+}
+
+/// 读取服务器列表, 每行 "名称 地址 端口", ';' 开头为注释
+pub fn read_server_list<P: AsRef<Path> + Debug>(path: P) -> Vec<ServerInfo> {
+    let mut result = Vec::new();
+    for (i, line) in read_table_lines(&path, "服务器列表") {
+        let fields = line.split_whitespace().collect::<Vec<&str>>();
+        match fields.as_slice() {
+            [name, host, port] => match port.parse() {
+                Ok(port) => result.push(ServerInfo { name: String::from(*name), host: String::from(*host), port }),
+                Err(_) => warn!("服务器端口错误: {:?} 第 {} 行: {}", path, i, line),
+            },
+            _ => warn!("服务器列表格式错误: {:?} 第 {} 行: {}", path, i, line),
+        }
+    }
+    result
+}
+
+/// 表格的一行: [头部] KEY(参数) KEY(参数)..., 返回头部和大写的字段名及参数, 括号不匹配时为空
+pub(crate) fn parse_tagged_line(line: &str) -> Option<(&str, Vec<(String, &str)>)> {
+    let line = line.strip_prefix('[')?;
+    let (head, mut rest) = line.split_once(']')?;
+    let mut tags = Vec::new();
+    while let Some(start) = rest.find('(') {
+        let end = start + rest[start..].find(')')?;
+        tags.push((rest[..start].trim().to_uppercase(), rest[start + 1..end].trim()));
+        rest = &rest[end + 1..];
+    }
+    Some((head.trim(), tags))
+}
+
+/// 逗号分隔的数字参数
+fn parse_numbers<T: std::str::FromStr>(value: &str) -> Option<Vec<T>> {
+    value.split(',').map(|v| v.trim().parse::<T>().ok()).collect()
+}
+
+fn parse_map_info(line: &str) -> Option<MapInfo> {
+    let (head, tags) = parse_tagged_line(line)?;
+    let mut head = head.split_whitespace();
+    let code = head.next()?;
+    let title = head.collect::<Vec<&str>>().join(" ");
+    let mut info = MapInfo::new(code, &title);
+    for (key, value) in tags {
+        match key.as_str() {
+            "FILE" => info.file = String::from(value),
+            "DATA" => match parse_numbers::<u32>(value)?.as_slice() {
+                [id, number] => (info.data_id, info.data_number) = (*id, *number),
+                _ => return None,
+            },
+            "MINIMAP" => info.minimap = Some(value.parse().ok()?),
+            "LIGHT" => info.light = match value.to_uppercase().as_str() {
+                "DAY" => LightMode::Day,
+                "DAWN" => LightMode::Dawn,
+                "NIGHT" => LightMode::Night,
+                "DARK" => LightMode::Dark,
+                _ => return None,
+            },
+            "MUSIC" => info.music = Some(String::from(value)),
+            "START" => match parse_numbers::<i32>(value)?.as_slice() {
+                [x, y] => info.start = Some((*x, *y)),
+                _ => return None,
+            },
+            _ => warn!("地图表未知字段: {}", key),
+        }
+    }
+    Some(info)
+}
+
+fn parse_object_shadow(line: &str) -> Option<ObjectShadow> {
+    let (head, tags) = parse_tagged_line(line)?;
+    let (file, range) = head.split_once(char::is_whitespace)?;
+    let (start, end) = range.trim().split_once('-')?;
+    let mut shadow = ObjectShadow {
+        file: file.parse().ok()?,
+        start: start.trim().parse().ok()?,
+        end: end.trim().parse().ok()?,
+        offset: None,
+    };
+    if shadow.start > shadow.end {
+        return None;
+    }
+    for (key, value) in tags {
+        match key.as_str() {
+            "SHADOW" => shadow.offset = Some(value.parse().ok()?),
+            _ => warn!("物件影子表未知字段: {}", key),
+        }
+    }
+    Some(shadow)
 }
 
 pub fn read_image<P: AsRef<Path> + Debug>(path: P, start: u32, end: u32) -> Option<ImageData> {
@@ -142,6 +606,23 @@ pub fn read_image<P: AsRef<Path> + Debug>(path: P, start: u32, end: u32) -> Opti
     Some(ImageData::from(&data[..]))
 }
 
+/// 只读取图片头中的宽高, 用于在加载图片前得到尺寸, offsets 为图片在 .wzl 中的位置
+pub fn read_image_sizes<P: AsRef<Path> + Debug>(path: P, offsets: &[u32]) -> Vec<(u32, u32)> {
+    let file = File::open(&path);
+    if file.is_err() {
+        error!("未找到资源文件: {:?}", path);
+        return Vec::new();
+    }
+    let mut reader = BufReader::new(file.unwrap());
+    let mut head = [0u8; 16];
+    offsets.iter().filter_map(|offset| {
+        reader.seek(SeekFrom::Start(*offset as u64)).ok()?;
+        reader.read_exact(&mut head).ok()?;
+        let mut body = &head[4..8];
+        Some((body.get_u16_le() as u32, body.get_u16_le() as u32))
+    }).collect()
+}
+
 pub fn read_index<P: AsRef<Path> + Debug>(path: P) -> Vec<u32> {
     let file =File::open(&path);
     if file.is_err() {
@@ -263,3 +744,167 @@ const PALETTE_RGBA: [u8; 1024] = [
     0x8C,0x7B,0x9C,0xFF,0x77,0x22,0xCC,0xFF,0xDD,0xAA,0xFF,0xFF,0xF0,0xB4,0x2A,0xFF,0xDF,0x00,0x9F,0xFF,0xE3,0x17,0xB3,0xFF,0xFF,0xFB,0xF0,0xFF,0xA0,0xA0,0xA4,0xFF,
     0x80,0x80,0x80,0xFF,0xFF,0x00,0x00,0xFF,0x00,0xFF,0x00,0xFF,0xFF,0xFF,0x00,0xFF,0x00,0x00,0xFF,0xFF,0xFF,0x00,0xFF,0xFF,0x00,0xFF,0xFF,0xFF,0xFF,0xFF,0xFF,0xFF,
 ];
+
+fn parse_actor_info(line: &str, races: &HashMap<String, HashMap<ActorAction, ActionFrames>>) -> Option<ActorInfo> {
+    let (head, tags) = parse_tagged_line(line)?;
+    let mut info = match head.strip_prefix('@') {
+        Some(_) => ActorInfo::new(0, head),
+        None => {
+            let (appearance, name) = head.split_once(char::is_whitespace).unwrap_or((head, ""));
+            ActorInfo::new(appearance.parse().ok()?, name.trim())
+        }
+    };
+    let layer = |value: &str| match value.split_once(',') {
+        Some((file, start)) => Some(ActorLayerInfo { file: String::from(file.trim()), start: start.trim().parse().ok()? }),
+        None => Some(ActorLayerInfo { file: String::from(value), start: 0 }),
+    };
+    for (key, value) in tags {
+        if let Some(action) = ActorAction::from_key(&key) {
+            match parse_numbers::<u32>(value)?.as_slice() {
+                [start, frames, skip, time] => info.actions.insert(action, ActionFrames::new(*start, *frames, *skip, *time as f32 / 1000.)),
+                _ => return None,
+            };
+        } else {
+            match key.as_str() {
+                "FILE" => info.file = String::from(value),
+                "START" => info.start = value.parse().ok()?,
+                "RACE" => match races.get(&value.to_uppercase()) {
+                    Some(actions) => actions.iter().for_each(|(action, frames)| {
+                        info.actions.entry(*action).or_insert(*frames);
+                    }),
+                    None => warn!("外观表未定义的帧布局: {}", value),
+                },
+                "SHADOW" => info.shadow = Some(layer(value)?),
+                "EFFECT" => info.effect = Some(layer(value)?),
+                _ => warn!("外观表未知字段: {}", key),
+            }
+        }
+    }
+    Some(info)
+}
+
+fn parse_effect_info(line: &str) -> Option<EffectInfo> {
+    let (head, tags) = parse_tagged_line(line)?;
+    let (id, name) = head.split_once(char::is_whitespace).unwrap_or((head, ""));
+    let mut info = EffectInfo::new(id.parse().ok()?, name.trim());
+    let mut directions = info.frames.directions;
+    for (key, value) in tags {
+        match key.as_str() {
+            "FILE" => info.file = String::from(value),
+            "FRAMES" => match parse_numbers::<u32>(value)?.as_slice() {
+                [start, frames, skip, time] => info.frames = ActionFrames::new(*start, *frames, *skip, *time as f32 / 1000.),
+                _ => return None,
+            },
+            "DIR" => directions = match value.parse().ok()? {
+                directions @ (1 | 8 | 16) => directions,
+                _ => return None,
+            },
+            "LIFE" => info.life = Some(value.parse::<u32>().ok()? as f32 / 1000.),
+            "SPEED" => info.speed = value.parse().ok()?,
+            "HIT" => info.hit = Some(value.parse().ok()?),
+            "BLEND" => info.blend = SpriteBlend::from_name(value)?,
+            _ => warn!("效果表未知字段: {}", key),
+        }
+    }
+    info.frames = info.frames.with_directions(directions);
+    Some(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn door_tile(door_idx: u8, door_offset: u8) -> Tile {
+        Tile { back: 0, middle: 0, objects: 0, door_idx, door_offset, frame: 0, tick: 0, light: 0, objects_idx: 0, back_idx: 0, middle_idx: 0 }
+    }
+
+    #[test]
+    fn doors_grouped_and_block_when_closed() {
+        // 1x4 的地图, 门 2 的阻挡格和装饰格, 门 3 只有一格
+        let mut map = MapData::new(1, 4, vec![door_tile(0x82, 4), door_tile(0x02, 4), door_tile(0, 0), door_tile(0x83, 0x82)]);
+        assert_eq!(map.doors.len(), 2);
+        assert_eq!(map.doors[&2], vec![0, 1]);
+        assert!(!map.can_walk(0, 0) && map.can_walk(0, 1) && map.can_walk(0, 2));
+        assert!(map.is_door_open(3) && map.can_walk(0, 3));
+
+        assert!(map.set_door_open(0x82, true));
+        assert!(map.is_door_open(2) && map.can_walk(0, 0));
+        assert_eq!(map.tile(0, 1).unwrap().door_frame_offset(), 4);
+        assert!(map.set_door_open(3, false));
+        assert!(!map.can_walk(0, 3));
+        assert!(!map.set_door_open(5, true));
+    }
+
+    #[test]
+    fn tagged_line() {
+        let (head, tags) = parse_tagged_line("[1 鸡] file(Mon1) START( 0 )").unwrap();
+        assert_eq!(head, "1 鸡");
+        assert_eq!(tags, vec![(String::from("FILE"), "Mon1"), (String::from("START"), "0")]);
+        assert!(parse_tagged_line("1 鸡 FILE(Mon1)").is_none());
+        assert!(parse_tagged_line("[1 鸡] FILE(Mon1").is_none());
+    }
+
+    #[test]
+    fn actor_info_with_race() {
+        let race = parse_actor_info("[@MA10] STAND(0,4,6,200) WALK(80,6,4,160)", &HashMap::new()).unwrap();
+        assert_eq!(race.name, "@MA10");
+        let races = HashMap::from([(String::from("MA10"), race.actions)]);
+        let info = parse_actor_info("[3 稻草人] FILE(Mon2) START(360) RACE(ma10) WALK(90,6,4,100) SHADOW(Mon2Shadow, 20)", &races).unwrap();
+        assert_eq!((info.appearance, info.name.as_str(), info.file.as_str(), info.start), (3, "稻草人", "Mon2", 360));
+        assert_eq!(info.actions[&ActorAction::Stand], ActionFrames::new(0, 4, 6, 0.2));
+        // RACE 之后的动作覆盖帧布局
+        assert_eq!(info.actions[&ActorAction::Walk], ActionFrames::new(90, 6, 4, 0.1));
+        assert_eq!(info.shadow, Some(ActorLayerInfo { file: String::from("Mon2Shadow"), start: 20 }));
+        assert_eq!(info.effect, None);
+    }
+
+    #[test]
+    fn actor_info_errors() {
+        let races = HashMap::new();
+        assert!(parse_actor_info("[x 鸡] FILE(Mon1)", &races).is_none());
+        assert!(parse_actor_info("[1 鸡] STAND(0,4)", &races).is_none());
+        assert!(parse_actor_info("[1 鸡] SHADOW(Mon1,x)", &races).is_none());
+        // 未定义的帧布局只记录警告
+        assert!(parse_actor_info("[1 鸡] FILE(Mon1) RACE(NONE)", &races).is_some_and(|info| info.actions.is_empty()));
+    }
+
+    #[test]
+    fn effect_info() {
+        let info = parse_effect_info("[1 火球术] FILE(Magic) FRAMES(1820,6,4,100) DIR(16) SPEED(400) HIT(2)").unwrap();
+        assert_eq!((info.id, info.name.as_str(), info.file.as_str()), (1, "火球术", "Magic"));
+        assert_eq!(info.frames, ActionFrames::new(1820, 6, 4, 0.1).with_directions(16));
+        assert_eq!((info.speed, info.hit, info.life), (400., Some(2), None));
+        assert!(info.is_projectile());
+
+        let info = parse_effect_info("[3 魔法盾] FILE(Magic) FRAMES(3890,3,0,150) LIFE(3000)").unwrap();
+        assert_eq!(info.frames.directions, 1);
+        assert_eq!(info.life, Some(3.));
+        assert!(!info.is_projectile());
+        assert_eq!(info.blend, SpriteBlend::Additive);
+        assert_eq!(parse_effect_info("[4 暗影] FILE(Magic) BLEND(multiply)").unwrap().blend, SpriteBlend::Multiply);
+        assert!(parse_effect_info("[4 暗影] FILE(Magic) BLEND(dye)").is_none());
+
+        assert!(parse_effect_info("[4 错误] FRAMES(1,2,3,4) DIR(5)").is_none());
+        assert!(parse_effect_info("[5 错误] FRAMES(1,2,3)").is_none());
+    }
+
+    #[test]
+    fn object_shadow() {
+        let shadow = parse_object_shadow("[2 1200-1260] SHADOW(100)").unwrap();
+        assert_eq!(shadow, ObjectShadow { file: 2, start: 1200, end: 1260, offset: Some(100) });
+        assert!(shadow.contains(2, 1260) && !shadow.contains(2, 1261) && !shadow.contains(1, 1200));
+        assert_eq!(parse_object_shadow("[1 0-350]").unwrap().offset, None);
+        assert!(parse_object_shadow("[1 350-0]").is_none());
+        assert!(parse_object_shadow("[1 350]").is_none());
+    }
+
+    #[test]
+    fn server_list_lines() {
+        let path = std::env::temp_dir().join(format!("mir2_servers_{}.txt", std::process::id()));
+        std::fs::write(&path, "; 名称 地址 端口\n\n  一区 127.0.0.1 7000\n二区 127.0.0.1 70000\n三区 localhost\n").unwrap();
+        assert_eq!(read_table_lines(&path, "服务器列表"), vec![(3, String::from("一区 127.0.0.1 7000")), (4, String::from("二区 127.0.0.1 70000")), (5, String::from("三区 localhost"))]);
+        assert_eq!(read_server_list(&path), vec![ServerInfo { name: String::from("一区"), host: String::from("127.0.0.1"), port: 7000 }]);
+        std::fs::remove_file(&path).unwrap();
+        assert!(read_table_lines(&path, "服务器列表").is_empty());
+    }
+}
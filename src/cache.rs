@@ -1,11 +1,12 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::{sync, thread};
 use std::ops::{Deref, Index};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::sync::mpsc::{Receiver, Sender};
-use std::time::Duration;
-use ggez::{Context, GameError, GameResult, input};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::time::{Duration, Instant};
+use ggez::{Context, GameError, GameResult};
 use ggez::glam::vec2;
 use ggez::graphics::{Canvas, Color, DrawParam, Image, ImageFormat};
 use moka::sync::Cache;
@@ -73,9 +74,15 @@ impl ImageMark {
     }
 }
 
+/// 每次重建图集分配一个新的版本号, 从 1 开始
+static IMAGE_GENERATION: AtomicU64 = AtomicU64::new(1);
+
 pub struct ImageValue {
     image: Image,
-    meta: HashMap<u32, ImageMeta>
+    meta: HashMap<u32, ImageMeta>,
+    max_width: u32,
+    max_height: u32,
+    generation: u64,
 }
 
 impl ImageValue {
@@ -84,6 +91,15 @@ impl ImageValue {
         self.image.clone()
     }
 
+    /// 图集的版本号, 加入新图片重建图集后变化, 用于判断缓存的绘制结果是否过期
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn max_size(&self) -> (u32, u32) {
+        (self.max_width, self.max_height)
+    }
+
     pub fn meta(&self, key: CacheMetaKey) -> Option<&ImageMeta> {
         self.meta.get(&key)
     }
@@ -92,13 +108,26 @@ impl ImageValue {
 type CacheDataKey = u32;
 type CacheMetaKey = u32;
 
+/// default_names 之后的文件编号由 add_name 按需分配
+const DYNAMIC_FILE_ID: u32 = 16;
+
+/// 发给加载线程的请求, 预取带着发出时的代数, 代数变化后丢弃
+enum LoadRequest {
+    Load(Vec<CacheKey>),
+    Prefetch(u64, Vec<CacheKey>),
+}
+
 pub struct ImageCache {
     names: Cache<u32, String>,
     key_mark: Cache<CacheDataKey, ImageMark>,
     key_image: Cache<CacheDataKey, Arc<ImageValue>>,
     // temp_image: Cache<CacheDataKey, Arc<Vec<(ImageMeta, ImageData)>>>,
-    load_sender: Sender<Vec<CacheKey>>,
-    load_receiver: Receiver<(CacheDataKey, Vec<(ImageMeta, ImageData)>)>
+    load_sender: Sender<LoadRequest>,
+    load_receiver: Receiver<(CacheDataKey, Vec<(ImageMeta, ImageData)>)>,
+    prefetch_generation: Arc<AtomicU64>,
+    load_latency: Arc<AtomicU64>,
+    file_ids: HashMap<String, u32>,
+    next_file_id: u32,
 }
 
 impl ImageCache {
@@ -107,30 +136,57 @@ impl ImageCache {
         let key_image = Cache::builder().time_to_idle(Duration::from_secs(5 * 60)).build();
         let key_mark = Cache::builder().time_to_idle(Duration::from_secs(5 * 60)).build();
         // let temp_image = Cache::builder().time_to_live(Duration::from_secs(1 * 60)).build();
-        let (load_sender, receiver) = sync::mpsc::channel::<Vec<CacheKey>>();
+        let (load_sender, receiver) = sync::mpsc::channel::<LoadRequest>();
         let (sender, load_receiver) = sync::mpsc::channel::<(CacheDataKey, Vec<(ImageMeta, ImageData)>)>();
-        let mut names = Cache::new(255);
-        names.insert(1, String::from("tiles"));
-        names.insert(2, String::from("smTiles"));
-        names.insert(3, String::from("objects"));
+        let prefetch_generation = Arc::new(AtomicU64::new(0));
+        let load_latency = Arc::new(AtomicU64::new(0));
+        let names = default_names();
         let mut k = key_image.clone();
         // let mut t = temp_image.clone();
         let mut index: HashMap<u32, Vec<u32>> = HashMap::new();
         let mut m = key_mark.clone();
         let n = names.clone();
+        let generation = prefetch_generation.clone();
+        let latency = load_latency.clone();
         let cache = Self {
             names,
             key_mark,
             key_image,
             load_sender,
             load_receiver,
+            prefetch_generation,
+            load_latency,
+            file_ids: HashMap::new(),
+            next_file_id: DYNAMIC_FILE_ID,
         };
 
 
         thread::spawn(move || {
+            let mut load = |keys: Vec<CacheKey>| {
+                let time = Instant::now();
+                draw_image(&mut index, &mut m, &mut k, sender.clone(), keys, &n, data_dir.clone());
+                // 加载耗时的滑动平均(微秒)
+                let elapsed = time.elapsed().as_micros() as u64;
+                let old = latency.load(Ordering::Relaxed);
+                latency.store(if old == 0 { elapsed } else { (old * 7 + elapsed) / 8 }, Ordering::Relaxed);
+            };
+            // 预取先放进队列, 通道里没有正常加载请求时才处理一批, 没有积压的预取时阻塞等待
+            let mut prefetches = VecDeque::new();
             loop {
-                if let Ok(keys) = receiver.recv() {
-                    draw_image(&mut index, &mut m, &mut k, sender.clone(), keys, &n, data_dir.clone())
+                let request = if prefetches.is_empty() {
+                    receiver.recv().map_err(|_| TryRecvError::Disconnected)
+                } else {
+                    receiver.try_recv()
+                };
+                match request {
+                    Ok(LoadRequest::Load(keys)) => load(keys),
+                    Ok(LoadRequest::Prefetch(g, keys)) => prefetches.push_back((g, keys)),
+                    Err(TryRecvError::Empty) => if let Some((g, keys)) = prefetches.pop_front() {
+                        if g == generation.load(Ordering::Relaxed) {
+                            load(keys);
+                        }
+                    },
+                    Err(TryRecvError::Disconnected) => break,
                 }
             }
         });
@@ -138,20 +194,60 @@ impl ImageCache {
         cache
     }
 
-    pub fn add_name(&mut self, key: u32, name: String) {
-        self.names.insert(key, name);
+    /// 注册图片库名并分配文件编号, 同名的图片库共用一个编号. 文件编号只有 8 位, 用完时返回错误
+    pub fn add_name(&mut self, name: &str) -> GameResult<u32> {
+        if let Some(id) = self.file_ids.get(name) {
+            return Ok(*id);
+        }
+        if self.next_file_id > FILE_ID_BITS {
+            return Err(GameError::ResourceLoadError(format!("图片库文件编号已用完, 无法注册: {}", name)));
+        }
+        let id = self.next_file_id;
+        self.next_file_id += 1;
+        self.names.insert(id, String::from(name));
+        self.file_ids.insert(String::from(name), id);
+        Ok(id)
     }
 
     pub fn load_keys(&mut self, keys: &[CacheKey]) {
-        self.load_sender.send(keys.to_vec()).unwrap();
+        self.load_sender.send(LoadRequest::Load(keys.to_vec())).unwrap();
     }
     pub fn load_key(&mut self, key: CacheKey) {
-        self.load_sender.send(vec![key]).unwrap();
+        self.load_sender.send(LoadRequest::Load(vec![key])).unwrap();
+    }
+
+    /// 卸载图集, 切换地图时释放旧地图的贴图
+    pub fn unload(&mut self, data_keys: &[CacheDataKey]) {
+        data_keys.iter().for_each(|key| {
+            self.key_image.invalidate(key);
+            self.key_mark.invalidate(key);
+        });
+    }
+
+    /// 低优先级加载, 只在没有正常加载请求时处理
+    pub fn prefetch_keys(&mut self, keys: &[CacheKey]) {
+        if keys.is_empty() {
+            return;
+        }
+        let generation = self.prefetch_generation.load(Ordering::Relaxed);
+        self.load_sender.send(LoadRequest::Prefetch(generation, keys.to_vec())).unwrap();
+    }
+
+    pub fn cancel_prefetch(&mut self) {
+        self.prefetch_generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn load_latency(&self) -> Duration {
+        Duration::from_micros(self.load_latency.load(Ordering::Relaxed))
     }
 
     fn insert_key(&mut self, ctx: &mut Context) {
         self.load_receiver.try_iter().for_each(|(data_key, data)| {
-            let mark = self.key_mark.get(&data_key).unwrap();
+            // 加载途中被卸载的图集直接丢弃
+            let mark = match self.key_mark.get(&data_key) {
+                Some(mark) => mark,
+                None => return,
+            };
             let max_height = (mark.next_height / 2000 + 1 ) * 2000;
             let image = Image::new_canvas_image(ctx, ImageFormat::Rgba8UnormSrgb, 2000, max_height, 1);
             let mut canvas = Canvas::from_image(ctx, image.clone(), Color::from_rgba(0, 0, 0, 0));
@@ -172,7 +268,9 @@ impl ImageCache {
             });
 
             canvas.finish(ctx).unwrap();
-            self.key_image.insert(data_key, Arc::new(ImageValue { image, meta: meta_image}))
+            let max_width = meta_image.values().map(|m| m.width).max().unwrap_or(0);
+            let max_height = meta_image.values().map(|m| m.height).max().unwrap_or(0);
+            self.key_image.insert(data_key, Arc::new(ImageValue { image, meta: meta_image, max_width, max_height, generation: IMAGE_GENERATION.fetch_add(1, Ordering::Relaxed) }))
         });
     }
 
@@ -222,7 +320,56 @@ fn draw_image<T: AsRef<Path>>(index: &mut HashMap<u32, Vec<u32>>,
 
 }
 
-fn load_image0<T: AsRef<Path>>(index: &mut HashMap<u32, Vec<u32>>, key: CacheKey, names: &Cache<u32, String>, data_dir: T) -> ImageData {
+/// 外观表, 效果表等引用的图片库名, 注册到 ImageCache 后才有文件编号
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveNames {
+    ids: HashMap<String, Option<u32>>,
+    pending: bool,
+}
+
+impl ArchiveNames {
+    pub fn insert(&mut self, name: &str) {
+        if !self.ids.contains_key(name) {
+            self.ids.insert(String::from(name), None);
+            self.pending = true;
+        }
+    }
+
+    /// 注册还没有编号的图片库, 编号用完的图片库不再重试, 其中的图片不显示
+    pub fn register(&mut self, cache: &mut ImageCache) {
+        if !std::mem::take(&mut self.pending) {
+            return;
+        }
+        for (name, id) in self.ids.iter_mut().filter(|(_, id)| id.is_none()) {
+            match cache.add_name(name) {
+                Ok(file_id) => *id = Some(file_id),
+                Err(e) => error!("图片库注册失败: {}", e),
+            }
+        }
+    }
+
+    pub fn id(&self, name: &str) -> Option<u32> {
+        self.ids.get(name).copied().flatten()
+    }
+}
+
+/// 文件编号到资源文件名的默认映射
+pub fn default_names() -> Cache<u32, String> {
+    let names = Cache::new(255);
+    names.insert(1, String::from("tiles"));
+    names.insert(2, String::from("smTiles"));
+    names.insert(3, String::from("objects"));
+    names.insert(4, String::from("mmap"));
+    names.insert(5, String::from("Prguse"));
+    names.insert(6, String::from("Prguse2"));
+    names.insert(7, String::from("ChrSel"));
+    names.insert(8, String::from("Hum"));
+    names.insert(9, String::from("Hair"));
+    names.insert(10, String::from("Weapon"));
+    names
+}
+
+pub(crate) fn load_image0<T: AsRef<Path>>(index: &mut HashMap<u32, Vec<u32>>, key: CacheKey, names: &Cache<u32, String>, data_dir: T) -> ImageData {
     let data_type = key.get_data_type();
     let file_idx = key.get_file_index();
     //如果没有找到名称映射表
@@ -270,7 +417,7 @@ fn load_image0<T: AsRef<Path>>(index: &mut HashMap<u32, Vec<u32>>, key: CacheKey
     ImageData::default()
 }
 
-fn get_file_name<T: AsRef<Path>>(dir: &T, file_name: &str, file_number: u32, data_type: u32) -> Option<PathBuf> {
+pub(crate) fn get_file_name<T: AsRef<Path>>(dir: &T, file_name: &str, file_number: u32, data_type: u32) -> Option<PathBuf> {
     let name = if file_number > 1 {
         format!("{}{}", file_name, file_number)
     } else {
@@ -320,6 +467,7 @@ impl CacheKey {
     }
 
     pub fn from(data_id: u32, data_number: u32, data_type: u32, data_count: u32, file_id: u32, file_number: u32, file_index: u32) -> Self {
+        debug_assert!(data_id <= DATA_ID_BITS && data_number <= DATA_NUMBER_BITS, "data_id {} 或 data_number {} 超出范围", data_id, data_number);
         let data_key = (data_id & DATA_ID_BITS) << 23 | (data_number & DATA_NUMBER_BITS) << 13 | (data_type & DATA_TYPE_BITS) << 10 | (data_count & DATA_COUNT_BITS);
         let meta_key = (file_id & FILE_ID_BITS) << FILE_ID_SHR | (file_number & FILE_NUMBER_BITS) << FILE_NUMBER_SHR | file_index & FILE_INDEX_BITS;
         Self { long_key: (data_key as u64) << 32 | meta_key as u64 }
@@ -0,0 +1,431 @@
+use std::collections::VecDeque;
+use crate::asset::{ActorAction, ActorInfo};
+use crate::cache::ImageCache;
+use crate::draw::actor::{ActorFrame, ActorSprite};
+use crate::draw::camera;
+use crate::draw::order::RenderQueue;
+use crate::draw::player::{PlayerAction, PlayerFrame};
+use crate::easing::{self, Easing, Point2};
+
+/// 排队的动作上限, 超过时丢弃最早的, 避免网络抖动时动作越积越多
+const MAX_QUEUED: usize = 3;
+
+/// 角色的动画状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ActorState {
+    Stand,
+    Walk,
+    Run,
+    Attack,
+    Spell,
+    Hit,
+    Die,
+    /// 死亡后停在最后一帧
+    Dead,
+}
+
+impl ActorState {
+    const COUNT: usize = 8;
+
+    /// 站立循环播放, 其他状态播放一遍
+    pub fn is_looping(&self) -> bool {
+        matches!(self, ActorState::Stand)
+    }
+
+    /// next 能否打断当前状态, 不能时排队等当前状态结束.
+    /// 死亡打断一切, 被攻击打断攻击和施法, 移动中和死亡后不能被打断
+    pub fn can_interrupt(&self, next: ActorState) -> bool {
+        match (self, next) {
+            (ActorState::Dead, _) => false,
+            (ActorState::Die, _) => false,
+            (_, ActorState::Die) => true,
+            (ActorState::Stand, _) => true,
+            (ActorState::Attack | ActorState::Spell, ActorState::Hit) => true,
+            _ => false,
+        }
+    }
+
+    pub fn player_action(&self) -> PlayerAction {
+        match self {
+            ActorState::Stand => PlayerAction::Stand,
+            ActorState::Walk => PlayerAction::Walk,
+            ActorState::Run => PlayerAction::Run,
+            ActorState::Attack => PlayerAction::Hit,
+            ActorState::Spell => PlayerAction::Spell,
+            ActorState::Hit => PlayerAction::Struck,
+            ActorState::Die | ActorState::Dead => PlayerAction::Die,
+        }
+    }
+
+    /// 怪物没有跑和施法, 分别用走和攻击代替
+    pub fn actor_action(&self) -> ActorAction {
+        match self {
+            ActorState::Stand => ActorAction::Stand,
+            ActorState::Walk | ActorState::Run => ActorAction::Walk,
+            ActorState::Attack | ActorState::Spell => ActorAction::Attack,
+            ActorState::Hit => ActorAction::Struck,
+            ActorState::Die => ActorAction::Die,
+            ActorState::Dead => ActorAction::Death,
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+/// 各状态的帧数和每帧时长(秒)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ActorTiming {
+    frames: [(u32, f64); ActorState::COUNT],
+}
+
+impl ActorTiming {
+    const STATES: [ActorState; ActorState::COUNT] = [
+        ActorState::Stand, ActorState::Walk, ActorState::Run, ActorState::Attack,
+        ActorState::Spell, ActorState::Hit, ActorState::Die, ActorState::Dead,
+    ];
+
+    /// 人物使用标准帧布局
+    pub fn player() -> Self {
+        let mut frames = [(1, 0.1); ActorState::COUNT];
+        for state in Self::STATES {
+            let f = state.player_action().frames();
+            frames[state.index()] = (f.frames, f.frame_time as f64);
+        }
+        frames[ActorState::Dead.index()] = (1, 1.);
+        Self { frames }
+    }
+
+    /// 怪物和 NPC 使用外观表中的帧布局
+    pub fn from_info(info: &ActorInfo) -> Self {
+        let mut frames = [(1, 0.1); ActorState::COUNT];
+        for state in Self::STATES {
+            if let Some(f) = info.frames(state.actor_action()) {
+                frames[state.index()] = (f.frames.max(1), f.frame_time as f64);
+            }
+        }
+        Self { frames }
+    }
+
+    pub fn frames(&self, state: ActorState) -> u32 {
+        self.frames[state.index()].0
+    }
+
+    pub fn frame_time(&self, state: ActorState) -> f64 {
+        self.frames[state.index()].1
+    }
+
+    pub fn duration(&self, state: ActorState) -> f64 {
+        self.frames(state) as f64 * self.frame_time(state)
+    }
+}
+
+/// 要求角色执行的动作, 方向为 0~7(正上开始顺时针)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActorCommand {
+    Stand,
+    Turn(u8),
+    Walk(u8),
+    Run(u8),
+    Attack(u8),
+    Spell(u8),
+    Hit,
+    Die,
+}
+
+impl ActorCommand {
+    fn state(&self) -> ActorState {
+        match self {
+            ActorCommand::Stand | ActorCommand::Turn(_) => ActorState::Stand,
+            ActorCommand::Walk(_) => ActorState::Walk,
+            ActorCommand::Run(_) => ActorState::Run,
+            ActorCommand::Attack(_) => ActorState::Attack,
+            ActorCommand::Spell(_) => ActorState::Spell,
+            ActorCommand::Hit => ActorState::Hit,
+            ActorCommand::Die => ActorState::Die,
+        }
+    }
+}
+
+/// 正在进行的移动, 偏移从 0 插值到 (dx, dy) * tiles 格
+struct Movement {
+    dx: i32,
+    dy: i32,
+    tiles: i32,
+    easing: Easing<Point2>,
+}
+
+/// 角色动画控制: 当前状态和帧, 动作队列, 格子间的移动插值, 由场景的 update 推进
+pub struct ActorAnimation {
+    tile_x: i32,
+    tile_y: i32,
+    direction: u8,
+    state: ActorState,
+    frame: u32,
+    elapsed: f64,
+    timing: ActorTiming,
+    queue: VecDeque<ActorCommand>,
+    movement: Option<Movement>,
+}
+
+impl ActorAnimation {
+    pub fn new(tile_x: i32, tile_y: i32, direction: u8, timing: ActorTiming) -> Self {
+        Self {
+            tile_x,
+            tile_y,
+            direction: direction % 8,
+            state: ActorState::Stand,
+            frame: 0,
+            elapsed: 0.,
+            timing,
+            queue: VecDeque::new(),
+            movement: None,
+        }
+    }
+
+    /// 能打断当前状态时立即执行, 否则排队
+    pub fn push(&mut self, command: ActorCommand) {
+        // 站立时转向不重置站立动画
+        if let (ActorState::Stand, ActorCommand::Turn(direction)) = (self.state, command) {
+            self.direction = direction % 8;
+            return;
+        }
+        if self.state.can_interrupt(command.state()) {
+            self.queue.clear();
+            self.start(command);
+        } else if self.state != ActorState::Dead {
+            if self.queue.len() >= MAX_QUEUED {
+                self.queue.pop_front();
+            }
+            self.queue.push_back(command);
+        }
+    }
+
+    fn start(&mut self, command: ActorCommand) {
+        let state = command.state();
+        self.movement = None;
+        self.frame = 0;
+        self.elapsed = 0.;
+        match command {
+            ActorCommand::Turn(direction) | ActorCommand::Attack(direction) | ActorCommand::Spell(direction) => self.direction = direction % 8,
+            ActorCommand::Walk(direction) | ActorCommand::Run(direction) => {
+                self.direction = direction % 8;
+                let (dx, dy) = easing::direction_offset(self.direction + 1);
+                let tiles = if state == ActorState::Run { 2 } else { 1 };
+                let finish = Point2::new((dx * tiles) as f32 * camera::TILE_WIDTH, (dy * tiles) as f32 * camera::TILE_HEIGHT);
+                let mut easing = Easing::new(Point2::default(), finish, self.timing.duration(state));
+                easing.run();
+                self.movement = Some(Movement { dx, dy, tiles, easing });
+            }
+            ActorCommand::Stand | ActorCommand::Hit | ActorCommand::Die => {}
+        }
+        self.state = state;
+    }
+
+    /// 推进动画, 移动结束时更新所在格子, 非循环状态结束后执行队列中的下一个动作
+    pub fn update(&mut self, delta: f64) {
+        if let Some(movement) = self.movement.as_mut() {
+            let finished = movement.easing.advance(delta);
+            let frames = self.timing.frames(self.state);
+            self.frame = ((movement.easing.progress() * frames as f64) as u32).min(frames.saturating_sub(1));
+            if finished {
+                self.tile_x += movement.dx * movement.tiles;
+                self.tile_y += movement.dy * movement.tiles;
+                self.movement = None;
+                self.finish();
+            }
+            return;
+        }
+        let frame_time = self.timing.frame_time(self.state);
+        self.elapsed += delta;
+        while frame_time > 0. && self.elapsed >= frame_time {
+            self.elapsed -= frame_time;
+            if self.frame + 1 < self.timing.frames(self.state) {
+                self.frame += 1;
+            } else if self.state.is_looping() {
+                self.frame = 0;
+            } else {
+                self.finish();
+                break;
+            }
+        }
+        // 站立时有排队的动作立即执行, 不等一轮播放完
+        if self.state == ActorState::Stand && !self.queue.is_empty() {
+            self.finish();
+        }
+    }
+
+    /// 当前状态结束: 死亡转为尸体, 其他执行下一个动作或回到站立
+    fn finish(&mut self) {
+        match self.state {
+            ActorState::Die => {
+                self.state = ActorState::Dead;
+                self.frame = 0;
+                self.queue.clear();
+            }
+            ActorState::Dead => {}
+            _ => match self.queue.pop_front() {
+                Some(command) => self.start(command),
+                None => self.start(ActorCommand::Stand),
+            },
+        }
+    }
+
+    /// 瞬移到格子, 取消移动和排队的动作
+    pub fn set_tile(&mut self, tile_x: i32, tile_y: i32) {
+        self.tile_x = tile_x;
+        self.tile_y = tile_y;
+        self.queue.clear();
+        if self.state != ActorState::Dead {
+            self.start(ActorCommand::Stand);
+        }
+    }
+
+    pub fn tile(&self) -> (i32, i32) {
+        (self.tile_x, self.tile_y)
+    }
+
+    pub fn state(&self) -> ActorState {
+        self.state
+    }
+
+    /// 站立且没有排队的动作
+    pub fn is_idle(&self) -> bool {
+        self.state == ActorState::Stand && self.queue.is_empty()
+    }
+
+    /// 这一步已经移动的像素
+    pub fn offset(&self) -> Point2 {
+        self.movement.as_ref().map_or(Point2::default(), |m| m.easing.now())
+    }
+
+    /// 格子左上角加上移动偏移的世界坐标, 精灵以此为原点
+    pub fn position(&self) -> Point2 {
+        let p = camera::tile_to_world(self.tile_x, self.tile_y);
+        let offset = self.offset();
+        Point2::new(p.x + offset.x, p.y + offset.y)
+    }
+
+    /// 格子中心加上移动偏移的世界坐标
+    pub fn center(&self) -> Point2 {
+        let p = camera::tile_center(self.tile_x, self.tile_y);
+        let offset = self.offset();
+        Point2::new(p.x + offset.x, p.y + offset.y)
+    }
+
+    /// 深度排序用的格子, 移动过半后算作下一格
+    pub fn depth_tile(&self) -> (i32, i32) {
+        let offset = self.offset();
+        (
+            self.tile_x + (offset.x / camera::TILE_WIDTH).round() as i32,
+            self.tile_y + (offset.y / camera::TILE_HEIGHT).round() as i32,
+        )
+    }
+
+    pub fn player_frame(&self) -> PlayerFrame {
+        let frame = match self.state {
+            ActorState::Dead => PlayerAction::Die.frames().frames.saturating_sub(1),
+            _ => self.frame,
+        };
+        PlayerFrame::new(self.state.player_action(), self.direction, frame)
+    }
+
+    pub fn actor_frame(&self) -> ActorFrame {
+        ActorFrame::new(self.state.actor_action(), self.direction, self.frame)
+    }
+}
+
+/// 地图上的怪物或 NPC: 外观表生成的精灵和动画
+pub struct MapActor {
+    pub sprite: ActorSprite,
+    pub anim: ActorAnimation,
+}
+
+impl MapActor {
+    pub fn new(sprite: ActorSprite, tile_x: i32, tile_y: i32, direction: u8) -> Self {
+        let timing = ActorTiming::from_info(sprite.info());
+        Self { sprite, anim: ActorAnimation::new(tile_x, tile_y, direction, timing) }
+    }
+
+    /// 加入深度队列, 与玩家相同按格子排序
+    pub fn queue(&mut self, cache: &mut ImageCache, queue: &mut RenderQueue) {
+        let p = self.anim.position();
+        self.sprite.queue(cache, queue, self.anim.depth_tile(), (p.x, p.y), self.anim.actor_frame());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn standing() -> ActorAnimation {
+        ActorAnimation::new(10, 10, 0, ActorTiming::player())
+    }
+
+    #[test]
+    fn walk_moves_tile() {
+        let mut anim = standing();
+        anim.push(ActorCommand::Walk(2));
+        assert_eq!((anim.state(), anim.direction), (ActorState::Walk, 2));
+        assert!(anim.movement.is_some());
+        anim.update(ActorTiming::player().duration(ActorState::Walk) + 0.01);
+        assert_eq!(anim.tile(), (11, 10));
+        assert!(anim.is_idle() && anim.movement.is_none());
+
+        anim.push(ActorCommand::Run(4));
+        anim.update(ActorTiming::player().duration(ActorState::Run) + 0.01);
+        assert_eq!(anim.tile(), (11, 12));
+    }
+
+    #[test]
+    fn queued_until_walk_ends() {
+        let mut anim = standing();
+        anim.push(ActorCommand::Walk(2));
+        anim.push(ActorCommand::Attack(4));
+        assert_eq!(anim.state(), ActorState::Walk);
+        anim.update(ActorTiming::player().duration(ActorState::Walk) + 0.01);
+        assert_eq!((anim.state(), anim.direction, anim.frame), (ActorState::Attack, 4, 0));
+        anim.update(ActorTiming::player().duration(ActorState::Attack) + 0.01);
+        assert_eq!(anim.state(), ActorState::Stand);
+    }
+
+    #[test]
+    fn hit_interrupts_attack() {
+        let mut anim = standing();
+        anim.push(ActorCommand::Attack(6));
+        anim.push(ActorCommand::Hit);
+        assert_eq!((anim.state(), anim.direction), (ActorState::Hit, 6));
+        // 被攻击时再被攻击排队
+        anim.push(ActorCommand::Hit);
+        assert_eq!(anim.state(), ActorState::Hit);
+    }
+
+    #[test]
+    fn turn_keeps_stand_frame() {
+        let mut anim = standing();
+        anim.update(ActorTiming::player().frame_time(ActorState::Stand) * 1.5);
+        assert_eq!(anim.frame, 1);
+        anim.push(ActorCommand::Turn(3));
+        assert_eq!((anim.state(), anim.direction, anim.frame), (ActorState::Stand, 3, 1));
+        // 站立循环播放
+        anim.update(ActorTiming::player().duration(ActorState::Stand) * 3.);
+        assert_eq!(anim.state(), ActorState::Stand);
+    }
+
+    #[test]
+    fn die_then_dead() {
+        let mut anim = standing();
+        anim.push(ActorCommand::Walk(2));
+        anim.push(ActorCommand::Die);
+        assert_eq!((anim.state(), anim.tile()), (ActorState::Die, (10, 10)));
+        anim.update(ActorTiming::player().duration(ActorState::Die) + 0.01);
+        assert_eq!(anim.state(), ActorState::Dead);
+        assert_eq!(anim.player_frame().frame, PlayerAction::Die.frames().frames - 1);
+
+        anim.push(ActorCommand::Walk(2));
+        anim.set_tile(20, 20);
+        anim.update(1.);
+        assert_eq!((anim.state(), anim.tile()), (ActorState::Dead, (20, 20)));
+    }
+}
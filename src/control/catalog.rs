@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+use std::path::Path;
+use tracing::{info, warn};
+use crate::asset::{self, MapInfo};
+use crate::control::transition::MapTarget;
+
+/// 地图表文件, 位于 map 目录下
+pub const MAP_INFO_FILE: &str = "mapinfo.txt";
+
+/// 逻辑地图编号到地图文件, 资源编号, 标题, 小地图, 光照和音乐的映射
+#[derive(Debug, Clone, Default)]
+pub struct MapCatalog {
+    maps: HashMap<String, MapInfo>,
+}
+
+impl MapCatalog {
+    pub fn load(base_dir: &Path) -> Self {
+        let mut catalog = Self::default();
+        for map in asset::read_map_info(base_dir.join("map").join(MAP_INFO_FILE)) {
+            if catalog.maps.contains_key(&map.code) {
+                warn!("地图编号重复: {}", map.code);
+            }
+            catalog.maps.insert(map.code.clone(), map);
+        }
+        info!("地图表已加载: {} 张地图", catalog.maps.len());
+        catalog
+    }
+
+    pub fn get(&self, code: &str) -> Option<&MapInfo> {
+        self.maps.get(code)
+    }
+
+    /// 按编号排序时 code 的下一张地图, 最后一张之后回到第一张
+    pub fn next_code(&self, code: &str) -> Option<&str> {
+        let mut codes = self.maps.keys().map(String::as_str).collect::<Vec<&str>>();
+        codes.sort_unstable();
+        let next = codes.iter().position(|c| *c == code).map_or(0, |i| (i + 1) % codes.len());
+        codes.get(next).copied()
+    }
+
+    /// 切换到 code 地图的目标, 未指定格子时使用地图表中的出生点, 都没有则为地图中心
+    pub fn target(&self, code: &str, tile: Option<(i32, i32)>) -> Option<MapTarget> {
+        let map = self.get(code)?;
+        let (tile_x, tile_y) = tile.or(map.start).unwrap_or((-1, -1));
+        Some(MapTarget {
+            code: map.code.clone(),
+            title: map.title.clone(),
+            data_id: map.data_id,
+            data_number: map.data_number,
+            name: map.file.clone(),
+            minimap: map.minimap,
+            tile_x,
+            tile_y,
+        })
+    }
+}
@@ -0,0 +1,165 @@
+use std::path::Path;
+use ggez::graphics::Color;
+use tracing::warn;
+use crate::asset::EffectInfo;
+use crate::cache::ImageCache;
+use crate::draw::blend::SpriteStyle;
+use crate::draw::camera;
+use crate::draw::effect::EffectTable;
+use crate::draw::order::RenderQueue;
+use crate::easing::{self, Point2};
+
+/// 飞行超过这个时间(秒)仍未命中时丢弃, 避免目标不可达时一直飞
+const MAX_FLIGHT_TIME: f64 = 5.;
+
+/// 效果的位置: 固定在格子上, 或跟随角色
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EffectAnchor {
+    Tile(i32, i32),
+    Actor(u32),
+}
+
+/// 正在播放的效果
+struct EffectInstance {
+    id: u32,
+    anchor: EffectAnchor,
+    /// 飞行中的位置(格子中心为准的世界坐标), 飞向 anchor
+    flying: Option<Point2>,
+    direction: u8,
+    elapsed: f64,
+}
+
+/// 魔法效果的播放: 挂在角色身上, 放在格子上, 或者飞向目标, 由场景的 update 推进.
+/// 角色的位置通过 locate 按编号查询(格子中心的世界坐标), 查不到时效果结束
+pub struct EffectSystem {
+    table: EffectTable,
+    effects: Vec<EffectInstance>,
+}
+
+impl EffectSystem {
+    pub fn new(table: EffectTable) -> Self {
+        Self { table, effects: Vec::new() }
+    }
+
+    pub fn load(base_dir: &Path) -> Self {
+        Self::new(EffectTable::load(base_dir))
+    }
+
+    pub fn table(&self) -> &EffectTable {
+        &self.table
+    }
+
+    pub fn play(&mut self, id: u32, anchor: EffectAnchor, direction: u8) {
+        self.spawn(id, anchor, None, direction)
+    }
+
+    /// 从 from 飞向目标, 到达后播放命中效果. 初始方向按目标当前位置计算
+    pub fn fire(&mut self, id: u32, from: Point2, target: EffectAnchor, locate: impl Fn(u32) -> Option<Point2>) {
+        let to = Self::resolve(target, &locate).unwrap_or(from);
+        let direction = self.table.get(id).map_or(0, |info| Self::direction(info, from, to));
+        self.spawn(id, target, Some(from), direction)
+    }
+
+    /// 按效果的方向数选择 from 指向 to 的方向, 16 方向用 angle16, 8 方向用 angle8, 从 0 开始
+    pub fn direction(info: &EffectInfo, from: Point2, to: Point2) -> u8 {
+        let angle = match info.frames.directions {
+            16 => easing::angle16(from.x, from.y, to.x, to.y),
+            8 => easing::angle8(from.x, from.y, to.x, to.y),
+            _ => return 0,
+        };
+        (angle as u8).saturating_sub(1)
+    }
+
+    fn spawn(&mut self, id: u32, anchor: EffectAnchor, flying: Option<Point2>, direction: u8) {
+        let info = match self.table.get(id) {
+            Some(info) => info,
+            None => {
+                warn!("效果表中没有该效果: {}", id);
+                return;
+            }
+        };
+        let flying = flying.filter(|_| info.is_projectile());
+        self.effects.push(EffectInstance { id, anchor, flying, direction, elapsed: 0. });
+    }
+
+    /// 结束挂在某个角色身上的效果, 如角色离开视野
+    pub fn stop_actor(&mut self, actor: u32) {
+        self.effects.retain(|e| e.anchor != EffectAnchor::Actor(actor));
+    }
+
+    pub fn clear(&mut self) {
+        self.effects.clear();
+    }
+
+    fn resolve(anchor: EffectAnchor, locate: &impl Fn(u32) -> Option<Point2>) -> Option<Point2> {
+        match anchor {
+            EffectAnchor::Tile(x, y) => Some(camera::tile_center(x, y)),
+            EffectAnchor::Actor(actor) => locate(actor),
+        }
+    }
+
+    /// 推进计时和飞行, 播放完的效果移除, 飞到目标的换成命中效果
+    pub fn update(&mut self, delta: f64, locate: impl Fn(u32) -> Option<Point2>) {
+        let mut hits = Vec::new();
+        let table = &self.table;
+        self.effects.retain_mut(|effect| {
+            let info = match table.get(effect.id) {
+                Some(info) => info,
+                None => return false,
+            };
+            effect.elapsed += delta;
+            let target = match Self::resolve(effect.anchor, &locate) {
+                Some(target) => target,
+                None => return false,
+            };
+            match effect.flying.as_mut() {
+                Some(position) => {
+                    let step = info.speed * delta as f32;
+                    if easing::distance(position.x, position.y, target.x, target.y) <= step {
+                        hits.extend(info.hit.map(|hit| (hit, effect.anchor, *position, target)));
+                        return false;
+                    }
+                    effect.direction = Self::direction(info, *position, target);
+                    let angle = (target.y - position.y).atan2(target.x - position.x);
+                    position.x += angle.cos() * step;
+                    position.y += angle.sin() * step;
+                    effect.elapsed < MAX_FLIGHT_TIME
+                }
+                None => effect.elapsed < info.life.unwrap_or(info.frames.duration()) as f64,
+            }
+        });
+        // 命中效果的方向数可能与飞行效果不同, 按它自己的方向数重新计算
+        for (hit, anchor, from, to) in hits {
+            let direction = self.table.get(hit).map_or(0, |info| Self::direction(info, from, to));
+            self.play(hit, anchor, direction);
+        }
+    }
+
+    /// 加入深度队列, 飞行和循环的效果循环播放帧, 其他停在最后一帧
+    pub fn queue(&mut self, cache: &mut ImageCache, queue: &mut RenderQueue, locate: impl Fn(u32) -> Option<Point2>) {
+        self.table.register(cache);
+        for effect in self.effects.iter() {
+            self.table.load_effect(cache, effect.id);
+        }
+        for effect in self.effects.iter() {
+            let info = match self.table.get(effect.id) {
+                Some(info) => info,
+                None => continue,
+            };
+            let center = match effect.flying {
+                Some(position) => position,
+                None => match Self::resolve(effect.anchor, &locate) {
+                    Some(center) => center,
+                    None => continue,
+                },
+            };
+            let frames = info.frames.frames.max(1);
+            let frame = if info.frames.frame_time > 0. { (effect.elapsed / info.frames.frame_time as f64) as u32 } else { 0 };
+            let frame = if effect.flying.is_some() || info.life.is_some() { frame % frames } else { frame.min(frames - 1) };
+            if let Some(key) = self.table.key(effect.id, effect.direction, frame) {
+                let (tile_x, tile_y) = camera::world_to_tile(center.x, center.y);
+                queue.push_styled(tile_x, tile_y, key, center.x - camera::TILE_WIDTH / 2., center.y - camera::TILE_HEIGHT / 2., SpriteStyle::new(info.blend, Color::WHITE));
+            }
+        }
+    }
+}
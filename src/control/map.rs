@@ -1,50 +1,593 @@
-use ggez::Context;
-use ggez::graphics::Canvas;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use ggez::{Context, GameError, GameResult};
+use ggez::event::MouseButton;
+use ggez::glam::vec2;
+use ggez::graphics::{Canvas, Color, DrawMode, DrawParam, Mesh, Quad, Rect};
+use ggez::input::keyboard::{KeyCode, KeyMods};
+use tracing::{error, info, warn};
+use crate::account::Gender;
+use crate::asset::MapInfo;
 use crate::cache::ImageCache;
 use crate::control::GameState;
+use crate::control::actor::{ActorAnimation, ActorCommand, ActorState, ActorTiming, MapActor};
+use crate::control::catalog::MapCatalog;
+use crate::control::effect::{EffectAnchor, EffectSystem};
+use crate::control::path;
+use crate::control::transition::{MAX_LOADING, MapEvent, MapTarget, MapTransition, SLOW_LOADING};
 use crate::draw;
+use crate::draw::actor::{ActorKind, ActorTable};
+use crate::draw::blend::Poison;
+use crate::draw::camera;
+use crate::draw::debug::MapDebugOverlay;
 use crate::draw::map::MapDraw;
-use crate::easing::{Easing, Point2};
+use crate::draw::minimap::Minimap;
+use crate::draw::player::{PlayerFrame, PlayerLook, PlayerSprite};
+use crate::draw::text::TextStyle;
+use crate::easing::{self, Point2};
+
+const DESTINATION_COLOR: Color = Color::new(1., 0.85, 0.2, 0.9);
+/// 名字底边在格子中心上方的距离
+const NAME_HEIGHT: f32 = 70.;
+/// 玩家在效果等按角色编号查找的地方使用的编号
+pub const PLAYER_ACTOR: u32 = 0;
+/// 按下到松开不超过这个时间且没有拖到别的格子时算点击, 否则是按住走路
+const CLICK_TIME: Duration = Duration::from_millis(250);
+/// 进入游戏时的地图在地图表中的编号
+pub const START_MAP: &str = "0";
 
 pub struct MapControl {
-    move_x: f32,
-    move_y: f32,
+    base_dir: PathBuf,
+    /// 玩家的格子, 方向, 动作和移动插值
+    player_anim: ActorAnimation,
+    /// 点击移动的剩余路线(不含当前格子)和终点
+    path: VecDeque<(i32, i32)>,
+    destination: Option<(i32, i32)>,
+    /// 左键按下的时间和格子, 松开时判断是否为点击
+    press: Option<(Instant, (i32, i32))>,
     map_title: String,
+    catalog: MapCatalog,
+    current_map: Option<MapInfo>,
     draw: MapDraw,
-    walk_easing: Easing<Point2>,
-    run_easing: Easing<Point2>,
+    minimap: Minimap,
+    show_world_map: bool,
+    transition: MapTransition,
+    loading_progress: f32,
+    events: VecDeque<MapEvent>,
+    player: PlayerSprite,
+    player_name: String,
+    effects: EffectSystem,
+    monsters: ActorTable,
+    npcs: ActorTable,
+    /// 地图上的怪物和 NPC, 编号从 1 开始, 0 为玩家
+    actors: HashMap<u32, MapActor>,
+    next_actor: u32,
 }
 
 impl MapControl {
 
-    pub fn new(state: &GameState) -> Self {
-        Self {
-            move_x: 0.,
-            move_y: 0.,
-            map_title: String::from(""),
-            draw: MapDraw::new(state.base_dir.as_path(), 1, 1, "n0", state.window_size.0, state.window_size.1),
-            walk_easing: Easing::new(Point2 {x: 0., y: 0.}, Point2 {x: 48., y: 32.}, 1.0),
-            run_easing: Easing::new(Point2 {x: 0., y: 0.}, Point2 {x: 96., y: 64.}, 1.0),
+    /// 进入角色上次所在的地图, 新角色或该地图不在地图表中时进入起始地图
+    pub fn new(state: &GameState) -> GameResult<Self> {
+        let catalog = MapCatalog::load(state.base_dir.as_path());
+        let character = state.session.as_ref().and_then(|s| s.character.as_ref());
+        let saved = character.and_then(|c| c.location.as_ref()).and_then(|(code, x, y)| {
+            let target = catalog.target(code, Some((*x, *y)));
+            if target.is_none() {
+                warn!("地图表中没有角色所在的地图: {}", code);
+            }
+            target
+        });
+        let target = match saved.or_else(|| catalog.target(START_MAP, None)) {
+            Some(target) => target,
+            None => {
+                error!("地图表中没有起始地图: {}", START_MAP);
+                return Err(GameError::CustomError(format!("地图表中没有起始地图: {}", START_MAP)));
+            }
+        };
+        let mut draw = MapDraw::new(state.base_dir.as_path(), target.data_id, target.data_number, &target.name, state.window_size.0, state.window_size.1);
+        let data = draw.map_data();
+        let (tile_x, tile_y) = if target.tile_x < 0 || target.tile_y < 0 { (data.width as i32 / 2, data.height as i32 / 2) } else { (target.tile_x, target.tile_y) };
+        let minimap = Minimap::request(state.base_dir.as_path(), &target.name, (data.width, data.height), target.data_id, target.data_number, target.minimap);
+        let map_title = if target.title.is_empty() { data.title.clone() } else { target.title.clone() };
+        draw.jump_by_tile(tile_x, tile_y, 0, 0);
+        let (player_x, player_y) = draw.current_tile();
+        Ok(Self {
+            base_dir: state.base_dir.clone(),
+            player_anim: ActorAnimation::new(player_x, player_y, 4, ActorTiming::player()),
+            path: VecDeque::new(),
+            destination: None,
+            press: None,
+            map_title,
+            current_map: catalog.get(&target.code).cloned(),
+            catalog,
+            draw,
+            minimap,
+            show_world_map: false,
+            transition: MapTransition::Idle,
+            loading_progress: 1.,
+            events: VecDeque::new(),
+            player: PlayerSprite::new(character.map_or(PlayerLook::new(0, 1, 0, Gender::Male), PlayerLook::from_character)),
+            player_name: character.map_or(String::new(), |c| c.name.clone()),
+            effects: EffectSystem::load(state.base_dir.as_path()),
+            monsters: ActorTable::load(state.base_dir.as_path(), ActorKind::Monster),
+            npcs: ActorTable::load(state.base_dir.as_path(), ActorKind::Npc),
+            actors: HashMap::new(),
+            next_actor: PLAYER_ACTOR + 1,
+        })
+    }
+
+    pub fn update(&mut self, ctx: &mut Context, cache: &mut ImageCache) {
+        let delta = ctx.time.delta().as_secs_f64();
+        self.update_transition(ctx, cache, delta);
+        if self.transition.is_idle() {
+            self.update_movement(ctx, delta);
+        }
+        self.actors.values_mut().for_each(|actor| actor.anim.update(delta));
+        let (player, actors) = (&self.player_anim, &self.actors);
+        self.effects.update(delta, |actor| Self::locate(player, actors, actor));
+        self.draw.update_move_pixel(delta);
+    }
+
+    /// 推进玩家动画, 走完一步且空闲后按输入开始下一步, 摄像机跟随玩家
+    fn update_movement(&mut self, ctx: &Context, delta: f64) {
+        self.player_anim.update(delta);
+        if self.player_anim.is_idle() {
+            let running = Self::is_running(ctx);
+            if let Some(direction) = Self::read_keys(ctx) {
+                self.clear_path();
+                self.start_step(direction, running);
+            } else if self.destination.is_some() {
+                self.follow_path(running);
+            } else if let Some(direction) = self.read_mouse(ctx) {
+                self.start_step(direction, running);
+            }
+        }
+        let p = self.player_position();
+        self.draw.follow(p.x, p.y);
+    }
+
+    /// 右键或 Ctrl 为跑
+    fn is_running(ctx: &Context) -> bool {
+        ctx.mouse.button_pressed(MouseButton::Right) || ctx.keyboard.is_mod_active(KeyMods::CTRL)
+    }
+
+    /// 方向键决定的方向
+    fn read_keys(ctx: &Context) -> Option<u8> {
+        let (mut dx, mut dy) = (0, 0);
+        for (key, x, y) in [(KeyCode::Up, 0, -1), (KeyCode::Down, 0, 1), (KeyCode::Left, -1, 0), (KeyCode::Right, 1, 0)] {
+            if ctx.keyboard.is_key_pressed(key) {
+                dx += x;
+                dy += y;
+            }
+        }
+        match easing::offset_direction(dx, dy) {
+            0 => None,
+            direction => Some(direction),
+        }
+    }
+
+    /// 按住鼠标时朝光标方向
+    fn read_mouse(&self, ctx: &Context) -> Option<u8> {
+        if !ctx.mouse.button_pressed(MouseButton::Left) && !ctx.mouse.button_pressed(MouseButton::Right) {
+            return None;
+        }
+        let mouse = ctx.mouse.position();
+        if self.draw.screen_to_tile(mouse.x, mouse.y) == self.player_tile() {
+            return None;
+        }
+        let p = self.player_position();
+        let p = self.draw.camera().world_to_screen(p.x, p.y);
+        Some(easing::angle8(p.x, p.y, mouse.x, mouse.y) as u8)
+    }
+
+    /// 按下左键时放弃原来的路线, 按住期间朝光标走
+    pub fn mouse_button_down(&mut self, button: MouseButton, x: f32, y: f32) {
+        if button == MouseButton::Left && self.transition.is_idle() {
+            self.clear_path();
+            self.press = Some((Instant::now(), self.draw.screen_to_tile(x, y)));
+        }
+    }
+
+    /// 短按且没有移到别的格子时为点击地面: 规划到该格子的路线
+    pub fn mouse_button_up(&mut self, button: MouseButton, x: f32, y: f32) {
+        if button != MouseButton::Left {
+            return;
+        }
+        if let Some((pressed, tile)) = self.press.take() {
+            if self.transition.is_idle() && pressed.elapsed() <= CLICK_TIME && self.draw.screen_to_tile(x, y) == tile {
+                self.move_to(tile.0, tile.1);
+            }
+        }
+    }
+
+    /// 寻路走到目标格子, 目标不可达时走到最近的格子
+    pub fn move_to(&mut self, tile_x: i32, tile_y: i32) {
+        if (tile_x, tile_y) == self.player_tile() {
+            self.clear_path();
+            return;
         }
+        self.destination = Some((tile_x, tile_y));
+        self.plan_path();
     }
 
-    pub fn update() {
+    fn plan_path(&mut self) {
+        let destination = match self.destination {
+            Some(destination) => destination,
+            None => return,
+        };
+        let draw = &self.draw;
+        let path = path::find_path(|x, y| draw.can_walk(x, y), self.player_anim.tile(), destination, path::MAX_SEARCH_NODES);
+        self.path = path.into();
+        if self.path.is_empty() {
+            self.destination = None;
+        }
+    }
+
+    pub fn clear_path(&mut self) {
+        self.path.clear();
+        self.destination = None;
+    }
+
+    /// 沿路线走下一步, 直线上连续两格且要跑时跑两格, 下一格被阻挡时重新规划
+    fn follow_path(&mut self, running: bool) {
+        let next = self.path.front().copied().filter(|(x, y)| self.draw.can_walk(*x, *y));
+        let next = match next {
+            Some(next) => next,
+            None if self.path.is_empty() => {
+                self.clear_path();
+                return;
+            }
+            None => {
+                self.plan_path();
+                match self.path.front() {
+                    Some(next) => *next,
+                    None => return,
+                }
+            }
+        };
+        let (player_x, player_y) = self.player_tile();
+        let (dx, dy) = (next.0 - player_x, next.1 - player_y);
+        let direction = easing::offset_direction(dx, dy);
+        if direction == 0 || dx.abs() > 1 || dy.abs() > 1 {
+            self.plan_path();
+            return;
+        }
+        let running = running && self.path.get(1) == Some(&(next.0 + dx, next.1 + dy));
+        let tiles = self.start_step(direction, running);
+        if tiles == 0 {
+            self.plan_path();
+            return;
+        }
+        self.path.drain(..tiles as usize);
+        if self.path.is_empty() {
+            self.destination = None;
+        }
+    }
+
+    /// 朝 direction 走一格或跑两格, 跑的第二格不可通行时改为走, 第一格不可通行时只转向. 返回移动的格子数
+    fn start_step(&mut self, direction: u8, running: bool) -> i32 {
+        let (dx, dy) = easing::direction_offset(direction);
+        let (player_x, player_y) = self.player_tile();
+        let sprite_direction = direction.saturating_sub(1);
+        if !self.draw.can_walk(player_x + dx, player_y + dy) {
+            self.player_anim.push(ActorCommand::Turn(sprite_direction));
+            return 0;
+        }
+        if running && self.draw.can_walk(player_x + dx * 2, player_y + dy * 2) {
+            self.player_anim.push(ActorCommand::Run(sprite_direction));
+            2
+        } else {
+            self.player_anim.push(ActorCommand::Walk(sprite_direction));
+            1
+        }
+    }
 
+    /// 玩家中心的世界坐标(像素), 包括这一步已经移动的距离
+    pub fn player_position(&self) -> Point2 {
+        self.player_anim.center()
+    }
+
+    pub fn player_tile(&self) -> (i32, i32) {
+        self.player_anim.tile()
+    }
+
+    /// 玩家朝目标格子施法: 飞行的效果从玩家飞向格子, 其他直接在格子上播放.
+    /// 格子上有角色时效果跟随角色. 玩家按 8 方向转身, 效果的方向按效果自己的方向数计算
+    pub fn cast(&mut self, effect: u32, tile_x: i32, tile_y: i32) {
+        let from = self.player_position();
+        let target = camera::tile_center(tile_x, tile_y);
+        self.player_anim.push(ActorCommand::Spell((easing::angle8(from.x, from.y, target.x, target.y) as u8).saturating_sub(1)));
+        let anchor = self.actor_at(tile_x, tile_y).map_or(EffectAnchor::Tile(tile_x, tile_y), EffectAnchor::Actor);
+        let (player, actors) = (&self.player_anim, &self.actors);
+        match self.effects.table().get(effect) {
+            Some(info) if info.is_projectile() => self.effects.fire(effect, from, anchor, |actor| Self::locate(player, actors, actor)),
+            Some(info) => {
+                let direction = EffectSystem::direction(info, from, target);
+                self.effects.play(effect, anchor, direction)
+            }
+            None => self.effects.play(effect, anchor, 0),
+        }
+    }
+
+    pub fn tile_at(&self, x: f32, y: f32) -> (i32, i32) {
+        self.draw.screen_to_tile(x, y)
+    }
+
+    pub fn debug(&mut self) -> &mut MapDebugOverlay {
+        self.draw.debug()
+    }
+
+    fn update_transition(&mut self, ctx: &mut Context, cache: &mut ImageCache, delta: f64) {
+        let transition = std::mem::replace(&mut self.transition, MapTransition::Idle);
+        self.transition = match transition {
+            MapTransition::Idle => MapTransition::Idle,
+            MapTransition::FadeOut { target, mut easing } => {
+                if easing.advance(delta) {
+                    self.load_map(cache, &target);
+                    self.events.push_back(MapEvent::Loading(target.clone()));
+                    MapTransition::loading(target)
+                } else {
+                    MapTransition::FadeOut { target, easing }
+                }
+            }
+            MapTransition::Loading { target, started, mut slow } => {
+                self.loading_progress = self.draw.loaded_ratio(ctx, cache);
+                if self.loading_progress >= 1. || started.elapsed() >= MAX_LOADING {
+                    info!("地图加载完成: {}, 耗时: {:?}, 进度: {}", target.name, started.elapsed(), self.loading_progress);
+                    self.events.push_back(MapEvent::FadeIn(target.clone()));
+                    MapTransition::fade_in(target)
+                } else {
+                    if !slow && started.elapsed() >= SLOW_LOADING {
+                        slow = true;
+                        self.events.push_back(MapEvent::LoadingSlow(target.clone()));
+                    }
+                    MapTransition::Loading { target, started, slow }
+                }
+            }
+            MapTransition::FadeIn { target, mut easing } => {
+                if easing.advance(delta) {
+                    self.events.push_back(MapEvent::Entered(target));
+                    MapTransition::Idle
+                } else {
+                    MapTransition::FadeIn { target, easing }
+                }
+            }
+        };
+    }
+
+    /// 卸载旧地图独占的图集, 读取新地图并把摄像机放到目标格子, 可见格子在下一次绘制时开始加载
+    fn load_map(&mut self, cache: &mut ImageCache, target: &MapTarget) {
+        let old_keys = self.draw.data_keys();
+        let (mut tile_x, mut tile_y) = (target.tile_x, target.tile_y);
+        self.draw.reload_map(target.data_id, target.data_number, &target.name, tile_x, tile_y, 0, 0);
+        if tile_x < 0 || tile_y < 0 {
+            let data = self.draw.map_data();
+            (tile_x, tile_y) = (data.width as i32 / 2, data.height as i32 / 2);
+            self.draw.jump_by_tile(tile_x, tile_y, 0, 0);
+        }
+        let (player_x, player_y) = self.draw.current_tile();
+        self.player_anim.set_tile(player_x, player_y);
+        self.effects.clear();
+        self.actors.clear();
+        self.clear_path();
+        let new_keys = self.draw.data_keys();
+        let unused = old_keys.iter().filter(|k| !new_keys.contains(k)).copied().collect::<Vec<u32>>();
+        cache.unload(unused.as_slice());
+        let data = self.draw.map_data();
+        self.minimap = Minimap::request(self.base_dir.as_path(), &target.name, (data.width, data.height), target.data_id, target.data_number, target.minimap);
+        self.current_map = self.catalog.get(&target.code).cloned();
+        self.map_title = if target.title.is_empty() { self.draw.map_data().title.clone() } else { target.title.clone() };
+        self.loading_progress = 0.;
+    }
+
+    /// 取出切换地图过程中产生的事件, 场景据此显示加载画面等
+    pub fn poll_event(&mut self) -> Option<MapEvent> {
+        self.events.pop_front()
     }
 
     pub fn draw(&mut self, ctx: &mut Context, canvas: &mut Canvas, state: &mut GameState, cache: &mut ImageCache) {
-        self.draw.draw_tile(canvas, ctx, cache, 255);
+        self.draw.draw_tile(canvas, ctx, cache);
+        let (tile, position, frame) = self.player_frame();
+        let (player, actors) = (&mut self.player, &mut self.actors);
+        self.draw.draw_objects(ctx, canvas, cache, |cache, queue| {
+            player.queue(cache, queue, tile, position, frame);
+            actors.values_mut().for_each(|actor| actor.queue(cache, queue));
+        });
+        let (effects, player_anim, actors) = (&mut self.effects, &self.player_anim, &self.actors);
+        self.draw.draw_effects(ctx, canvas, cache, |cache, queue| effects.queue(cache, queue, |actor| Self::locate(player_anim, actors, actor)));
+        self.draw_player_name(ctx, canvas, state);
+        self.draw_destination(ctx, canvas);
+        self.draw.draw_debug(ctx, canvas);
+        self.draw_minimap(ctx, canvas, state);
+        let alpha = self.transition.alpha();
+        if alpha > 0. {
+            canvas.draw(&Quad, DrawParam::default().dest(vec2(0., 0.)).scale(vec2(state.window_size.0, state.window_size.1)).color(Color::new(0., 0., 0., alpha)));
+        }
+    }
+
+    /// 角色编号对应的格子中心, 供效果跟随
+    fn locate(player: &ActorAnimation, actors: &HashMap<u32, MapActor>, actor: u32) -> Option<Point2> {
+        match actor {
+            PLAYER_ACTOR => Some(player.center()),
+            _ => actors.get(&actor).map(|a| a.anim.center()),
+        }
+    }
+
+    /// 在格子上放一个怪物或 NPC, 返回角色编号, 外观表中没有该外观时为空
+    pub fn spawn_actor(&mut self, cache: &mut ImageCache, kind: ActorKind, appearance: u32, tile_x: i32, tile_y: i32, direction: u8) -> Option<u32> {
+        let table = match kind {
+            ActorKind::Monster => &mut self.monsters,
+            ActorKind::Npc => &mut self.npcs,
+        };
+        table.register(cache);
+        let sprite = match table.sprite(appearance) {
+            Some(sprite) => sprite,
+            None => {
+                warn!("外观表中没有该外观: {:?} {}", kind, appearance);
+                return None;
+            }
+        };
+        let id = self.next_actor;
+        self.next_actor += 1;
+        self.actors.insert(id, MapActor::new(sprite, tile_x, tile_y, direction));
+        Some(id)
+    }
+
+    fn actor_at(&self, tile_x: i32, tile_y: i32) -> Option<u32> {
+        self.actors.iter().find(|(_, actor)| actor.anim.tile() == (tile_x, tile_y)).map(|(id, _)| *id)
+    }
+
+    /// 原地朝目标格子攻击, 前方相邻格子上的角色被击中
+    pub fn attack(&mut self, tile_x: i32, tile_y: i32) {
+        let from = self.player_position();
+        let target = camera::tile_center(tile_x, tile_y);
+        let direction = (easing::angle8(from.x, from.y, target.x, target.y) as u8).saturating_sub(1);
+        self.clear_path();
+        self.player_anim.push(ActorCommand::Attack(direction));
+        let (dx, dy) = easing::direction_offset(direction + 1);
+        let (player_x, player_y) = self.player_tile();
+        if let Some(id) = self.actor_at(player_x + dx, player_y + dy) {
+            self.command_actor(id, ActorCommand::Hit);
+        }
     }
 
-    pub fn select_map(&mut self, state: &GameState) {
-        self.draw.reload_map(2, 3, "n3", 333, 333, 0, 0);
+    /// 调试用: 格子上的角色死亡, 已经死亡的移除
+    pub fn kill_actor(&mut self, tile_x: i32, tile_y: i32) {
+        match self.actor_at(tile_x, tile_y) {
+            Some(id) if self.actors[&id].anim.state() == ActorState::Dead => self.remove_actor(id),
+            Some(id) => self.command_actor(id, ActorCommand::Die),
+            None => {}
+        }
     }
 
-    pub fn jump_map() {
+    /// 移除角色和挂在它身上的效果
+    pub fn remove_actor(&mut self, id: u32) {
+        self.actors.remove(&id);
+        self.effects.stop_actor(id);
+    }
 
+    /// 中毒状态由服务器通知, 整个角色染成对应颜色
+    pub fn set_player_poison(&mut self, poison: Poison) {
+        self.player.set_poison(poison);
     }
 
-    pub fn move_map() {
+    pub fn set_actor_poison(&mut self, id: u32, poison: Poison) {
+        if let Some(actor) = self.actors.get_mut(&id) {
+            actor.sprite.set_poison(poison);
+        }
+    }
 
+    /// 调试用: 格子上的角色切换中毒状态, 没有角色时切换玩家的
+    pub fn cycle_poison(&mut self, tile_x: i32, tile_y: i32) {
+        match self.actor_at(tile_x, tile_y) {
+            Some(id) => {
+                let poison = self.actors[&id].sprite.poison().next();
+                self.set_actor_poison(id, poison);
+            }
+            None => {
+                let poison = self.player.poison().next();
+                self.set_player_poison(poison);
+            }
+        }
     }
-}
\ No newline at end of file
+
+    /// 给角色下达动作, 如服务器通知的走路和攻击
+    pub fn command_actor(&mut self, id: u32, command: ActorCommand) {
+        if let Some(actor) = self.actors.get_mut(&id) {
+            actor.anim.push(command);
+        }
+    }
+
+    /// 玩家当前的动作帧, 以及用于深度排序的格子和绘制位置(格子左上角的世界坐标)
+    fn player_frame(&self) -> ((i32, i32), (f32, f32), PlayerFrame) {
+        let p = self.player_anim.position();
+        (self.player_anim.depth_tile(), (p.x, p.y), self.player_anim.player_frame())
+    }
+
+    fn draw_player_name(&mut self, ctx: &mut Context, canvas: &mut Canvas, state: &mut GameState) {
+        if self.player_name.is_empty() {
+            return;
+        }
+        let p = self.player_position();
+        let p = self.draw.camera().world_to_screen(p.x, p.y - NAME_HEIGHT);
+        state.text.draw_name(ctx, canvas, &self.player_name, p.x, p.y, Color::WHITE);
+    }
+
+    /// 点击移动的终点标记
+    fn draw_destination(&self, ctx: &mut Context, canvas: &mut Canvas) {
+        if let Some((tile_x, tile_y)) = self.destination {
+            let center = camera::tile_center(tile_x, tile_y);
+            let radius = (camera::TILE_WIDTH / 2. - 6., camera::TILE_HEIGHT / 2. - 4.);
+            if let Ok(mesh) = Mesh::new_ellipse(ctx, DrawMode::stroke(2.), vec2(center.x, center.y), radius.0, radius.1, 0.5, DESTINATION_COLOR) {
+                canvas.draw(&mesh, self.draw.draw_param());
+            }
+        }
+    }
+
+    /// 小地图下方显示地图名
+    fn draw_minimap(&mut self, ctx: &mut Context, canvas: &mut Canvas, state: &mut GameState) {
+        self.minimap.update(ctx);
+        let (window_width, window_height) = state.window_size;
+        if self.show_world_map {
+            let (w, h) = (window_width * 0.8, window_height * 0.8);
+            self.minimap.draw_world_map(canvas, Rect::new((window_width - w) / 2., (window_height - h) / 2., w, h), self.player_tile());
+        } else {
+            self.minimap.draw_minimap(canvas, Rect::new(window_width - 210., 10., 200., 150.), self.player_tile(), 1.);
+            state.text.draw_centered(ctx, canvas, &self.map_title, Rect::new(window_width - 210., 162., 200., 14.), TextStyle::new(12., Color::WHITE).outline());
+        }
+    }
+
+    pub fn toggle_world_map(&mut self) {
+        self.show_world_map = !self.show_world_map;
+    }
+
+    /// 按整数倍缩放地图, steps 为正时放大
+    pub fn zoom(&mut self, steps: i32) {
+        let zoom = self.draw.camera().zoom() as i32 + steps;
+        self.draw.set_zoom(zoom.max(0) as u32);
+    }
+
+    pub fn resize(&mut self, state: &GameState) {
+        self.draw.resize(state.window_size.0, state.window_size.1);
+    }
+
+    /// 按地图表中的编号切换地图, tile 为空时使用出生点
+    pub fn enter_map(&mut self, code: &str, tile: Option<(i32, i32)>) -> bool {
+        match self.catalog.target(code, tile) {
+            Some(target) => {
+                self.jump_map(target);
+                true
+            }
+            None => {
+                error!("地图表中没有该地图: {}", code);
+                false
+            }
+        }
+    }
+
+    /// 开关格子所在的门, 门的所有格子一起切换
+    pub fn toggle_door(&mut self, tile_x: i32, tile_y: i32) {
+        let door = self.draw.map_data().tile(tile_x, tile_y).map_or(0, |t| t.door_index());
+        if door == 0 {
+            info!("格子 ({}, {}) 没有门", tile_x, tile_y);
+            return;
+        }
+        let open = !self.draw.is_door_open(door);
+        self.draw.set_door_open(door, open);
+    }
+
+    /// 调试用: 依次进入地图表中的地图
+    pub fn enter_next_map(&mut self) {
+        let current = self.current_map.as_ref().map_or("", |m| m.code.as_str());
+        if let Some(code) = self.catalog.next_code(current).map(String::from) {
+            self.enter_map(&code, None);
+        }
+    }
+
+    /// 淡出 -> 卸载旧地图 -> 加载新地图并预加载可见格子 -> 淡入
+    pub fn jump_map(&mut self, target: MapTarget) {
+        self.events.push_back(MapEvent::FadeOut(target.clone()));
+        self.transition = MapTransition::fade_out(target);
+    }
+}
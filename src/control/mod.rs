@@ -1,7 +1,15 @@
-use std::path::{PathBuf};
+use std::path::{Path, PathBuf};
+use ggez::Context;
+use crate::account::Session;
 use crate::cache::ImageCache;
+use crate::draw::text::TextRenderer;
 
+pub mod actor;
+pub mod catalog;
+pub mod effect;
 pub mod map;
+pub mod path;
+pub mod transition;
 
 #[derive(Debug, Clone)]
 pub struct GameState {
@@ -10,6 +18,34 @@ pub struct GameState {
     pub(crate) screen_size: (f32, f32),
     pub(crate) window_size: (f32, f32),
     pub(crate) center_point: (f32, f32),
+    pub(crate) session: Option<Session>,
+    pub(crate) text: TextRenderer,
 }
 
+impl GameState {
+    pub fn new(base_dir: &Path, ctx: &mut Context) -> Self {
+        let (window_width, window_height) = ctx.gfx.drawable_size();
+        let mut state = Self {
+            base_dir: base_dir.to_path_buf(),
+            scale_factor: 1.,
+            screen_size: (window_width, window_height),
+            window_size: (window_width, window_height),
+            center_point: (window_width / 2., window_height / 2.),
+            session: None,
+            text: TextRenderer::load(ctx, base_dir),
+        };
+        state.resize(ctx, window_width, window_height);
+        state
+    }
 
+    /// 窗口大小或显示器(DPI)变化后刷新, width/height 为物理像素
+    pub fn resize(&mut self, ctx: &Context, width: f32, height: f32) {
+        let window = ctx.gfx.window();
+        self.scale_factor = window.scale_factor() as f32;
+        if let Some(monitor) = window.current_monitor() {
+            self.screen_size = (monitor.size().width as f32, monitor.size().height as f32);
+        }
+        self.window_size = (width, height);
+        self.center_point = (width / 2., height / 2.);
+    }
+}
@@ -0,0 +1,105 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use crate::easing;
+
+/// 寻路最多展开的格子数, 防止目标不可达时搜索整张地图
+pub const MAX_SEARCH_NODES: usize = 20000;
+
+fn heuristic(from: (i32, i32), to: (i32, i32)) -> i32 {
+    (to.0 - from.0).abs().max((to.1 - from.1).abs())
+}
+
+/// 八方向 A* 寻路, 斜走与直走代价相同. 目标不可达或超出搜索上限时, 返回到离目标最近的已搜索格子的路线.
+/// 返回的路线不含起点
+pub fn find_path(can_walk: impl Fn(i32, i32) -> bool, start: (i32, i32), goal: (i32, i32), max_nodes: usize) -> Vec<(i32, i32)> {
+    let mut open = BinaryHeap::new();
+    let mut came_from: HashMap<(i32, i32), (i32, i32)> = HashMap::new();
+    let mut cost: HashMap<(i32, i32), i32> = HashMap::new();
+    let mut best = (heuristic(start, goal), start);
+    open.push(Reverse((best.0, best.0, start)));
+    cost.insert(start, 0);
+
+    let mut searched = 0;
+    while let Some(Reverse((_, h, current))) = open.pop() {
+        if current == goal {
+            best = (0, current);
+            break;
+        }
+        if h < best.0 {
+            best = (h, current);
+        }
+        searched += 1;
+        if searched >= max_nodes {
+            break;
+        }
+        let g = cost[&current] + 1;
+        for direction in 1..=8 {
+            let (dx, dy) = easing::direction_offset(direction);
+            let next = (current.0 + dx, current.1 + dy);
+            if !can_walk(next.0, next.1) || cost.get(&next).is_some_and(|c| *c <= g) {
+                continue;
+            }
+            cost.insert(next, g);
+            came_from.insert(next, current);
+            let h = heuristic(next, goal);
+            open.push(Reverse((g + h, h, next)));
+        }
+    }
+
+    let mut path = Vec::new();
+    let mut node = best.1;
+    while node != start {
+        path.push(node);
+        node = came_from[&node];
+    }
+    path.reverse();
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_adjacent(a: (i32, i32), b: (i32, i32)) -> bool {
+        (a.0 - b.0).abs() <= 1 && (a.1 - b.1).abs() <= 1 && a != b
+    }
+
+    #[test]
+    fn straight_and_diagonal() {
+        // 斜走与直走代价相同, 只检查步数和终点
+        let path = find_path(|_, _| true, (0, 0), (3, 0), MAX_SEARCH_NODES);
+        assert_eq!((path.len(), path.last()), (3, Some(&(3, 0))));
+        assert_eq!(find_path(|_, _| true, (0, 0), (2, 2), MAX_SEARCH_NODES), vec![(1, 1), (2, 2)]);
+        assert!(find_path(|_, _| true, (4, 4), (4, 4), MAX_SEARCH_NODES).is_empty());
+    }
+
+    #[test]
+    fn around_wall() {
+        // x = 1 这一列只在 y = 3 有缺口
+        let can_walk = |x: i32, y: i32| (-5..=5).contains(&x) && (-5..=5).contains(&y) && (x != 1 || y == 3);
+        let path = find_path(can_walk, (0, 0), (2, 0), MAX_SEARCH_NODES);
+        assert_eq!(path.last(), Some(&(2, 0)));
+        assert!(path.contains(&(1, 3)));
+        let mut previous = (0, 0);
+        for step in path {
+            assert!(is_adjacent(previous, step) && can_walk(step.0, step.1));
+            previous = step;
+        }
+    }
+
+    #[test]
+    fn unreachable_goal_stops_nearest() {
+        let can_walk = |x: i32, y: i32| (-3..3).contains(&x) && (-3..=3).contains(&y);
+        let path = find_path(can_walk, (0, 0), (5, 0), MAX_SEARCH_NODES);
+        let last = *path.last().unwrap();
+        assert_eq!(heuristic(last, (5, 0)), 3);
+        assert!(path.iter().all(|p| can_walk(p.0, p.1)));
+    }
+
+    #[test]
+    fn search_limit() {
+        let path = find_path(|_, _| true, (0, 0), (1000, 0), 10);
+        assert!(!path.is_empty() && path.len() < 10);
+        assert!(path.windows(2).all(|w| is_adjacent(w[0], w[1])));
+    }
+}
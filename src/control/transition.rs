@@ -0,0 +1,71 @@
+use std::time::{Duration, Instant};
+use crate::easing::Easing;
+
+pub const FADE_TIME: f64 = 0.3;
+/// 预加载超过该时间时通知场景显示加载画面
+pub const SLOW_LOADING: Duration = Duration::from_millis(500);
+/// 预加载最长等待时间, 超时后直接进入地图
+pub const MAX_LOADING: Duration = Duration::from_secs(10);
+
+/// 切换地图的目标
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapTarget {
+    pub code: String,
+    pub title: String,
+    pub data_id: u32,
+    pub data_number: u32,
+    pub name: String,
+    pub minimap: Option<u32>,
+    /// 小于 0 时进入地图中心
+    pub tile_x: i32,
+    pub tile_y: i32,
+}
+
+/// 切换地图过程中通知场景的事件
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapEvent {
+    FadeOut(MapTarget),
+    Loading(MapTarget),
+    /// 预加载较慢, 场景可以显示加载画面
+    LoadingSlow(MapTarget),
+    FadeIn(MapTarget),
+    Entered(MapTarget),
+}
+
+pub enum MapTransition {
+    Idle,
+    FadeOut { target: MapTarget, easing: Easing<f32> },
+    Loading { target: MapTarget, started: Instant, slow: bool },
+    FadeIn { target: MapTarget, easing: Easing<f32> },
+}
+
+impl MapTransition {
+    pub fn fade_out(target: MapTarget) -> Self {
+        let mut easing = Easing::new(0., 1., FADE_TIME);
+        easing.run();
+        MapTransition::FadeOut { target, easing }
+    }
+
+    pub fn loading(target: MapTarget) -> Self {
+        MapTransition::Loading { target, started: Instant::now(), slow: false }
+    }
+
+    pub fn fade_in(target: MapTarget) -> Self {
+        let mut easing = Easing::new(1., 0., FADE_TIME);
+        easing.run();
+        MapTransition::FadeIn { target, easing }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        matches!(self, MapTransition::Idle)
+    }
+
+    /// 遮罩的不透明度, 0 为完全可见
+    pub fn alpha(&self) -> f32 {
+        match self {
+            MapTransition::Idle => 0.,
+            MapTransition::FadeOut { easing, .. } | MapTransition::FadeIn { easing, .. } => easing.now(),
+            MapTransition::Loading { .. } => 1.,
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use tracing::{info, warn};
+use crate::asset::{self, ActionFrames, ActorAction, ActorInfo};
+use crate::cache::{ArchiveNames, CacheKey, ImageCache};
+use crate::draw::blend::{Poison, SpriteBlend, SpriteStyle};
+use crate::draw::camera;
+use crate::draw::order::RenderQueue;
+
+/// 怪物图集的 data_id, 身体/影子/特效各一个, data_number 为外观编号. data_id 只有 8 位
+const MONSTER_DATA_ID: u32 = 210;
+const NPC_DATA_ID: u32 = 213;
+
+/// 外观表的种类, 各自使用独立的文件编号和图集
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ActorKind {
+    #[default]
+    Monster,
+    Npc,
+}
+
+impl ActorKind {
+    pub fn table(&self) -> &'static str {
+        match self {
+            ActorKind::Monster => "config/monsters.txt",
+            ActorKind::Npc => "config/npcs.txt",
+        }
+    }
+
+    fn data_id(&self, layer: ActorLayer) -> u32 {
+        let base = match self {
+            ActorKind::Monster => MONSTER_DATA_ID,
+            ActorKind::Npc => NPC_DATA_ID,
+        };
+        base + match layer {
+            ActorLayer::Body => 0,
+            ActorLayer::Shadow => 1,
+            ActorLayer::Effect => 2,
+        }
+    }
+}
+
+/// 怪物和 NPC 的图层, 影子在所有物件和角色之前画, 然后画身体, 最后画特效
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ActorLayer {
+    Shadow,
+    Body,
+    Effect,
+}
+
+impl ActorLayer {
+    const ORDER: [ActorLayer; 3] = [ActorLayer::Shadow, ActorLayer::Body, ActorLayer::Effect];
+
+    /// 特效层叠加混合
+    fn blend(&self) -> SpriteBlend {
+        match self {
+            ActorLayer::Effect => SpriteBlend::Additive,
+            _ => SpriteBlend::Alpha,
+        }
+    }
+}
+
+/// 外观表, 按外观编号查找, 图片库名在注册时由 ImageCache 分配文件编号
+#[derive(Debug, Clone, Default)]
+pub struct ActorTable {
+    kind: ActorKind,
+    infos: HashMap<u32, ActorInfo>,
+    files: ArchiveNames,
+}
+
+impl ActorTable {
+    pub fn new(kind: ActorKind) -> Self {
+        Self { kind, ..Default::default() }
+    }
+
+    pub fn load(base_dir: &Path, kind: ActorKind) -> Self {
+        let mut result = Self::new(kind);
+        asset::read_actor_info(base_dir.join(kind.table())).into_iter().for_each(|info| result.insert(info));
+        info!("加载外观表: {} 共 {} 个", kind.table(), result.infos.len());
+        result
+    }
+
+    pub fn insert(&mut self, info: ActorInfo) {
+        let files = [Some(&info.file), info.shadow.as_ref().map(|l| &l.file), info.effect.as_ref().map(|l| &l.file)];
+        files.into_iter().flatten().for_each(|file| self.files.insert(file));
+        self.infos.insert(info.appearance, info);
+    }
+
+    /// 把图片库名注册到 ImageCache, 在取精灵之前调用
+    pub fn register(&mut self, cache: &mut ImageCache) {
+        self.files.register(cache);
+    }
+
+    /// 某个外观的精灵, 外观不存在时为空
+    pub fn sprite(&self, appearance: u32) -> Option<ActorSprite> {
+        let info = self.infos.get(&appearance)?;
+        let file_id = |file: &str| self.files.id(file);
+        let mut layers = vec![(ActorLayer::Body, file_id(&info.file)?, info.start)];
+        if let Some(shadow) = &info.shadow {
+            layers.extend(file_id(&shadow.file).map(|id| (ActorLayer::Shadow, id, shadow.start)));
+        }
+        if let Some(effect) = &info.effect {
+            layers.extend(file_id(&effect.file).map(|id| (ActorLayer::Effect, id, effect.start)));
+        }
+        layers.sort_by_key(|(layer, _, _)| ActorLayer::ORDER.iter().position(|l| l == layer));
+        let layers = layers.into_iter().map(|(layer, file_id, start)| (layer, self.kind.data_id(layer), file_id, start)).collect();
+        Some(ActorSprite { info: info.clone(), layers, poison: Poison::None, loaded: HashSet::new() })
+    }
+}
+
+/// 要画的一帧, direction 为 0~7
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActorFrame {
+    pub action: ActorAction,
+    pub direction: u8,
+    pub frame: u32,
+}
+
+impl ActorFrame {
+    pub fn new(action: ActorAction, direction: u8, frame: u32) -> Self {
+        Self { action, direction, frame }
+    }
+}
+
+/// 怪物或 NPC 的精灵: 按外观表把动作帧解析为各图层的图片
+#[derive(Debug, Clone)]
+pub struct ActorSprite {
+    info: ActorInfo,
+    /// 图层, data_id, 文件编号, 起始序号, 按绘制顺序
+    layers: Vec<(ActorLayer, u32, u32, u32)>,
+    poison: Poison,
+    loaded: HashSet<ActorAction>,
+}
+
+impl ActorSprite {
+    pub fn info(&self) -> &ActorInfo {
+        &self.info
+    }
+
+    pub fn frames(&self, action: ActorAction) -> Option<ActionFrames> {
+        self.info.frames(action)
+    }
+
+    pub fn poison(&self) -> Poison {
+        self.poison
+    }
+
+    pub fn set_poison(&mut self, poison: Poison) {
+        self.poison = poison;
+    }
+
+    /// 第一次用到某个动作时整段加载所有图层
+    pub fn load(&mut self, cache: &mut ImageCache, action: ActorAction) {
+        let frames = match self.frames(action) {
+            Some(frames) => frames,
+            None => return,
+        };
+        if !self.loaded.insert(action) {
+            return;
+        }
+        let appearance = self.info.appearance;
+        let keys = self.layers.iter()
+            .map(|(_, data_id, file_id, start)| CacheKey::from(*data_id, appearance, 2, frames.count(), *file_id, 1, start + frames.start))
+            .collect::<Vec<CacheKey>>();
+        cache.load_keys(keys.as_slice());
+    }
+
+    fn layers(&self, frame: ActorFrame) -> Vec<(ActorLayer, CacheKey)> {
+        let frames = match self.frames(frame.action) {
+            Some(frames) => frames,
+            None => {
+                warn!("外观没有动作: {} {:?}", self.info.appearance, frame.action);
+                return Vec::new();
+            }
+        };
+        let index = frames.index(frame.direction, frame.frame);
+        self.layers.iter()
+            .map(|(layer, data_id, file_id, start)| (*layer, CacheKey::from(*data_id, self.info.appearance, 2, 1, *file_id, 1, start + index)))
+            .collect()
+    }
+
+    /// 加入深度队列, position 为所在格子左上角的世界坐标.
+    /// 有影子图层时用影子帧, 没有时用身体生成影子
+    pub fn queue(&mut self, cache: &mut ImageCache, queue: &mut RenderQueue, tile: (i32, i32), position: (f32, f32), frame: ActorFrame) {
+        self.load(cache, frame.action);
+        let tint = self.poison.tint();
+        let layers = self.layers(frame);
+        let has_shadow = layers.iter().any(|(layer, _)| *layer == ActorLayer::Shadow);
+        for (layer, key) in layers {
+            match layer {
+                ActorLayer::Shadow => queue.push_shadow(tile.0, tile.1, key, position.0, position.1),
+                ActorLayer::Body if !has_shadow => {
+                    queue.push_silhouette(tile.0, tile.1, key, position.0, position.1, position.1 + camera::TILE_HEIGHT / 2.);
+                    queue.push_styled(tile.0, tile.1, key, position.0, position.1, SpriteStyle::new(layer.blend(), tint));
+                }
+                _ => queue.push_styled(tile.0, tile.1, key, position.0, position.1, SpriteStyle::new(layer.blend(), tint)),
+            }
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use ggez::{Context, GameResult};
+use ggez::graphics::{BlendMode, Color, Shader, ShaderBuilder};
+
+/// 精灵的混合方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SpriteBlend {
+    #[default]
+    Alpha,
+    /// 叠加, 用于魔法效果和光, 黑色部分不可见
+    Additive,
+    /// 相乘, 只会压暗, 白色部分不可见
+    Multiply,
+    /// 染色: 先去色再乘染色, 用于染发. 直接相乘只能压暗, 深色头发染不出浅色
+    Dye,
+}
+
+impl SpriteBlend {
+    pub fn mode(&self) -> BlendMode {
+        match self {
+            SpriteBlend::Alpha => BlendMode::ALPHA,
+            SpriteBlend::Additive => BlendMode::ADD,
+            SpriteBlend::Multiply => BlendMode::MULTIPLY,
+            SpriteBlend::Dye => BlendMode::ALPHA,
+        }
+    }
+
+    /// 表格中的混合方式名, 染色需要染色参数, 不能在表格中指定
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_uppercase().as_str() {
+            "ALPHA" => Some(SpriteBlend::Alpha),
+            "ADD" => Some(SpriteBlend::Additive),
+            "MULTIPLY" => Some(SpriteBlend::Multiply),
+            _ => None,
+        }
+    }
+}
+
+/// 染色的片段着色器: 取亮度并开方提亮暗部, 保留明暗层次, 再乘染色
+const DYE_SHADER: &str = r#"
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+@group(1) @binding(0)
+var t: texture_2d<f32>;
+
+@group(1) @binding(1)
+var s: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = textureSample(t, s, in.uv);
+    let luma = sqrt(dot(texel.rgb, vec3<f32>(0.299, 0.587, 0.114)));
+    return vec4<f32>(vec3<f32>(luma) * in.color.rgb, texel.a * in.color.a);
+}
+"#;
+
+pub fn dye_shader(ctx: &Context) -> GameResult<Shader> {
+    ShaderBuilder::new().fragment_code(DYE_SHADER).build(&ctx.gfx)
+}
+
+/// 单个精灵的混合方式和染色, 染色与图片颜色相乘
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpriteStyle {
+    pub blend: SpriteBlend,
+    pub tint: Color,
+}
+
+impl Default for SpriteStyle {
+    fn default() -> Self {
+        Self { blend: SpriteBlend::Alpha, tint: Color::WHITE }
+    }
+}
+
+impl SpriteStyle {
+    pub fn new(blend: SpriteBlend, tint: Color) -> Self {
+        Self { blend, tint }
+    }
+
+    pub fn tinted(tint: Color) -> Self {
+        Self::new(SpriteBlend::Alpha, tint)
+    }
+
+    /// 染成 color, 为空时保持原色
+    pub fn dyed(color: Option<Color>) -> Self {
+        match color {
+            Some(color) => Self::new(SpriteBlend::Dye, color),
+            None => Self::default(),
+        }
+    }
+
+    /// 影子: 染成半透明的黑色
+    pub fn shadow() -> Self {
+        Self::tinted(SHADOW_COLOR)
+    }
+
+    pub fn tint(self, tint: Color) -> Self {
+        Self { tint: mix(self.tint, tint), ..self }
+    }
+}
+
+const SHADOW_COLOR: Color = Color::new(0., 0., 0., 0.5);
+
+pub fn mix(a: Color, b: Color) -> Color {
+    Color::new(a.r * b.r, a.g * b.g, a.b * b.b, a.a * b.a)
+}
+
+/// 染发的颜色, 与去色后的头发相乘, 编号从 1 开始, 0 为原色
+const HAIR_DYES: [Color; 8] = [
+    Color::new(1., 0.45, 0.45, 1.),
+    Color::new(1., 0.7, 0.35, 1.),
+    Color::new(1., 1., 0.45, 1.),
+    Color::new(0.5, 1., 0.5, 1.),
+    Color::new(0.45, 0.85, 1., 1.),
+    Color::new(0.5, 0.55, 1., 1.),
+    Color::new(0.85, 0.5, 1., 1.),
+    Color::new(0.55, 0.55, 0.55, 1.),
+];
+
+/// 染发编号对应的颜色, 0 或超出范围时为原色
+pub fn hair_dye(dye: u8) -> Option<Color> {
+    HAIR_DYES.get((dye as usize).checked_sub(1)?).copied()
+}
+
+/// 中毒状态, 整个角色染成对应颜色
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Poison {
+    #[default]
+    None,
+    /// 绿毒, 持续掉血
+    Green,
+    /// 红毒, 防御下降
+    Red,
+}
+
+impl Poison {
+    /// 依次切换, 调试用
+    pub fn next(&self) -> Self {
+        match self {
+            Poison::None => Poison::Green,
+            Poison::Green => Poison::Red,
+            Poison::Red => Poison::None,
+        }
+    }
+
+    pub fn tint(&self) -> Color {
+        match self {
+            Poison::None => Color::WHITE,
+            Poison::Green => Color::new(0.45, 1., 0.45, 1.),
+            Poison::Red => Color::new(1., 0.4, 0.4, 1.),
+        }
+    }
+}
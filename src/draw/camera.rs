@@ -0,0 +1,186 @@
+use ggez::glam::vec2;
+use ggez::graphics::DrawParam;
+use crate::easing::{Easing, Point2};
+
+pub const TILE_WIDTH: f32 = 48.;
+pub const TILE_HEIGHT: f32 = 32.;
+pub const MIN_ZOOM: u32 = 1;
+pub const MAX_ZOOM: u32 = 4;
+
+/// 摄像机, position 为屏幕中心对应的世界像素坐标
+pub struct Camera {
+    position: Point2,
+    target: Point2,
+    follow: Option<Easing<Point2>>,
+    follow_time: f64,
+    zoom: u32,
+    window_width: f32,
+    window_height: f32,
+}
+
+impl Camera {
+    pub fn new(window_width: f32, window_height: f32) -> Self {
+        Self {
+            position: Point2::default(),
+            target: Point2::default(),
+            follow: None,
+            follow_time: 0.2,
+            zoom: MIN_ZOOM,
+            window_width,
+            window_height,
+        }
+    }
+
+    pub fn position(&self) -> Point2 {
+        self.position
+    }
+
+    pub fn window_size(&self) -> (f32, f32) {
+        (self.window_width, self.window_height)
+    }
+
+    pub fn set_window_size(&mut self, window_width: f32, window_height: f32) {
+        self.window_width = window_width;
+        self.window_height = window_height;
+    }
+
+    pub fn jump_to(&mut self, x: f32, y: f32) {
+        self.follow = None;
+        self.position = Point2::new(x, y);
+        self.target = self.position;
+    }
+
+    pub fn follow(&mut self, x: f32, y: f32) {
+        let target = Point2::new(x, y);
+        if target == self.target {
+            return;
+        }
+        self.target = target;
+        let mut easing = Easing::new(self.position, target, self.follow_time);
+        easing.run();
+        self.follow = Some(easing);
+    }
+
+    /// 推进跟随动画, 返回位置是否变化
+    pub fn update(&mut self, time: f64) -> bool {
+        if let Some(easing) = &mut self.follow {
+            let finished = easing.advance(time);
+            self.position = easing.now();
+            if finished {
+                self.follow = None;
+            }
+            return true;
+        }
+        false
+    }
+
+    pub fn zoom(&self) -> u32 {
+        self.zoom
+    }
+
+    pub fn set_zoom(&mut self, zoom: u32) {
+        self.zoom = zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+    }
+
+    pub fn view_size(&self) -> (f32, f32) {
+        (self.window_width / self.zoom as f32, self.window_height / self.zoom as f32)
+    }
+
+    /// 可见区域可容纳的格子数
+    pub fn view_tiles(&self) -> (i32, i32) {
+        let (w, h) = self.view_size();
+        ((w / TILE_WIDTH).ceil() as i32, (h / TILE_HEIGHT).ceil() as i32)
+    }
+
+    /// 屏幕左上角的世界坐标, 取整避免贴图缝隙
+    pub fn view_origin(&self) -> Point2 {
+        let (w, h) = self.view_size();
+        Point2::new((self.position.x - w / 2.).round(), (self.position.y - h / 2.).round())
+    }
+
+    pub fn current_tile(&self) -> (i32, i32) {
+        world_to_tile(self.position.x, self.position.y)
+    }
+
+    pub fn screen_to_world(&self, screen_x: f32, screen_y: f32) -> Point2 {
+        let origin = self.view_origin();
+        Point2::new(origin.x + screen_x / self.zoom as f32, origin.y + screen_y / self.zoom as f32)
+    }
+
+    pub fn world_to_screen(&self, world_x: f32, world_y: f32) -> Point2 {
+        let origin = self.view_origin();
+        Point2::new((world_x - origin.x) * self.zoom as f32, (world_y - origin.y) * self.zoom as f32)
+    }
+
+    pub fn screen_to_tile(&self, screen_x: f32, screen_y: f32) -> (i32, i32) {
+        let world = self.screen_to_world(screen_x, screen_y);
+        world_to_tile(world.x, world.y)
+    }
+
+    pub fn tile_to_screen(&self, tile_x: i32, tile_y: i32) -> Point2 {
+        let world = tile_to_world(tile_x, tile_y);
+        self.world_to_screen(world.x, world.y)
+    }
+
+    /// 把世界坐标绘制到屏幕的变换
+    pub fn draw_param(&self) -> DrawParam {
+        let origin = self.view_origin();
+        let zoom = self.zoom as f32;
+        DrawParam::default().dest(vec2(-origin.x * zoom, -origin.y * zoom)).scale(vec2(zoom, zoom))
+    }
+}
+
+pub fn world_to_tile(world_x: f32, world_y: f32) -> (i32, i32) {
+    ((world_x / TILE_WIDTH).floor() as i32, (world_y / TILE_HEIGHT).floor() as i32)
+}
+
+/// 格子左上角的世界坐标
+pub fn tile_to_world(tile_x: i32, tile_y: i32) -> Point2 {
+    Point2::new(tile_x as f32 * TILE_WIDTH, tile_y as f32 * TILE_HEIGHT)
+}
+
+pub fn tile_center(tile_x: i32, tile_y: i32) -> Point2 {
+    Point2::new(tile_x as f32 * TILE_WIDTH + TILE_WIDTH / 2., tile_y as f32 * TILE_HEIGHT + TILE_HEIGHT / 2.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera(zoom: u32) -> Camera {
+        let mut camera = Camera::new(960., 640.);
+        camera.jump_to(1000., 500.);
+        camera.set_zoom(zoom);
+        camera
+    }
+
+    #[test]
+    fn conversions_at_zoom_1() {
+        let camera = camera(1);
+        assert_eq!(camera.view_origin(), Point2::new(520., 180.));
+        assert_eq!(camera.screen_to_world(480., 320.), Point2::new(1000., 500.));
+        assert_eq!(camera.world_to_screen(568., 212.), Point2::new(48., 32.));
+        assert_eq!(camera.screen_to_tile(480., 320.), (20, 15));
+        assert_eq!(camera.tile_to_screen(20, 15), Point2::new(440., 300.));
+        assert_eq!(camera.view_tiles(), (20, 20));
+    }
+
+    #[test]
+    fn conversions_at_zoom_2() {
+        let camera = camera(2);
+        assert_eq!(camera.view_origin(), Point2::new(760., 340.));
+        assert_eq!(camera.screen_to_world(480., 320.), Point2::new(1000., 500.));
+        assert_eq!(camera.world_to_screen(1000., 500.), Point2::new(480., 320.));
+        assert_eq!(camera.screen_to_tile(0., 0.), (15, 10));
+        assert_eq!(camera.tile_to_screen(20, 15), Point2::new(400., 280.));
+        assert_eq!(camera.view_tiles(), (10, 10));
+    }
+
+    #[test]
+    fn zoom_clamped_and_tiles_floor() {
+        assert_eq!(camera(0).zoom(), MIN_ZOOM);
+        assert_eq!(camera(9).zoom(), MAX_ZOOM);
+        assert_eq!(world_to_tile(-1., -1.), (-1, -1));
+        assert_eq!(tile_center(1, 1), Point2::new(72., 48.));
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use ggez::Context;
+use ggez::glam::vec2;
+use ggez::graphics::{Canvas, Color, DrawParam, Image, ImageFormat, InstanceArray, Rect};
+use crate::cache::{CacheKey, ImageCache, ImageValue};
+use crate::draw::camera;
+use crate::draw::map::{MapDraw, MapTileSet};
+
+pub const CHUNK_TILES: i32 = 16;
+
+/// 烘焙好的区块, 只记录烘焙时图集的版本号(0 为未加载), 不持有图集, 旧图集可以及时释放
+struct GroundChunk {
+    image: Image,
+    back: u64,
+    middle: u64,
+    complete: bool,
+}
+
+/// 地表层(大地砖+小地砖)按区块烘焙成贴图, 只有在区块的图片加载完成或地图切换时才重建
+#[derive(Default)]
+pub struct GroundChunks {
+    chunks: HashMap<(i32, i32), GroundChunk>,
+}
+
+impl GroundChunks {
+    pub fn new() -> Self {
+        Self { chunks: HashMap::new() }
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+    }
+
+    /// 区块左上角的世界坐标
+    fn origin(chunk_x: i32, chunk_y: i32) -> (f32, f32) {
+        let p = camera::tile_to_world(chunk_x * CHUNK_TILES, chunk_y * CHUNK_TILES);
+        (p.x, p.y)
+    }
+
+    /// 区块内需要绘制的格子, 多取左上一格以包含跨区块的 96x64 大地砖
+    fn tile_sets(map: &MapDraw, chunk_x: i32, chunk_y: i32) -> Vec<MapTileSet> {
+        let start_x = chunk_x * CHUNK_TILES - 1;
+        let start_y = chunk_y * CHUNK_TILES - 1;
+        let mut sets = Vec::new();
+        for x in start_x..start_x + CHUNK_TILES + 1 {
+            for y in start_y..start_y + CHUNK_TILES + 1 {
+                if let Some(set) = map.build_tile_set(x, y, true) {
+                    sets.push(set);
+                }
+            }
+        }
+        sets
+    }
+
+    fn load(cache: &mut ImageCache, sets: &[MapTileSet]) {
+        let back_keys = sets.iter().filter(|t| t.has_back()).map(|t| t.back_key).collect::<Vec<CacheKey>>();
+        let middle_keys = sets.iter().filter(|t| t.has_middle()).map(|t| t.middle_key).collect::<Vec<CacheKey>>();
+        cache.load_keys(back_keys.as_slice());
+        cache.load_keys(middle_keys.as_slice());
+    }
+
+    fn instances(ctx: &mut Context, value: &ImageValue, items: impl Iterator<Item = (CacheKey, f32, f32)>) -> (InstanceArray, usize) {
+        let image_width = value.image().width() as f32;
+        let image_height = value.image().height() as f32;
+        let mut array = InstanceArray::new(ctx, value.image());
+        let mut count = 0;
+        array.set(items
+            .filter_map(|(key, x, y)| value.meta(key.get_meta_key()).map(|meta| (meta, x, y)))
+            .map(|(meta, x, y)| {
+                count += 1;
+                DrawParam::default().src(Rect::new(meta.src_x / image_width, meta.src_y / image_height, meta.width as f32 / image_width, meta.height as f32 / image_height))
+                    .dest(vec2(meta.offset_x + x, meta.offset_y + y))
+            }));
+        (array, count)
+    }
+
+    fn generation(value: &Option<Arc<ImageValue>>) -> u64 {
+        value.as_ref().map_or(0, |v| v.generation())
+    }
+
+    fn bake(ctx: &mut Context, back: &Option<Arc<ImageValue>>, middle: &Option<Arc<ImageValue>>, sets: &[MapTileSet], chunk_x: i32, chunk_y: i32) -> GroundChunk {
+        let width = (CHUNK_TILES as f32 * camera::TILE_WIDTH) as u32;
+        let height = (CHUNK_TILES as f32 * camera::TILE_HEIGHT) as u32;
+        let image = Image::new_canvas_image(ctx, ImageFormat::Rgba8UnormSrgb, width, height, 1);
+        let mut canvas = Canvas::from_image(ctx, image.clone(), Color::from_rgba(0, 0, 0, 0));
+        let (origin_x, origin_y) = Self::origin(chunk_x, chunk_y);
+        let dest = DrawParam::default().dest(vec2(-origin_x, -origin_y));
+
+        let expected = sets.iter().filter(|t| t.has_back()).count() + sets.iter().filter(|t| t.has_middle()).count();
+        let mut loaded = 0;
+        if let Some(value) = back {
+            let (array, count) = Self::instances(ctx, value, sets.iter().filter(|t| t.has_back()).map(|t| (t.back_key, t.x, t.y)));
+            canvas.draw(&array, dest);
+            loaded += count;
+        }
+        if let Some(value) = middle {
+            let (array, count) = Self::instances(ctx, value, sets.iter().filter(|t| t.has_middle()).map(|t| (t.middle_key, t.x, t.y)));
+            canvas.draw(&array, dest);
+            loaded += count;
+        }
+        canvas.finish(ctx).unwrap();
+        GroundChunk { image, back: Self::generation(back), middle: Self::generation(middle), complete: loaded >= expected }
+    }
+
+    /// 绘制覆盖 tile 范围的区块, 缺失或图片有更新的未完成区块会被(重新)烘焙
+    pub fn draw(&mut self, ctx: &mut Context, canvas: &mut Canvas, cache: &mut ImageCache, map: &MapDraw, tiles: (i32, i32, i32, i32)) {
+        let (start_x, start_y, end_x, end_y) = tiles;
+        let chunk_x = start_x.div_euclid(CHUNK_TILES)..=end_x.div_euclid(CHUNK_TILES);
+        let chunk_y = start_y.div_euclid(CHUNK_TILES)..=end_y.div_euclid(CHUNK_TILES);
+        // 离开视野一个区块以上的直接释放
+        self.chunks.retain(|(x, y), _| {
+            *x >= chunk_x.start() - 1 && *x <= chunk_x.end() + 1 && *y >= chunk_y.start() - 1 && *y <= chunk_y.end() + 1
+        });
+
+        let (back_data_key, middle_data_key) = map.ground_data_keys();
+        let back = cache.get(ctx, &back_data_key);
+        let middle = cache.get(ctx, &middle_data_key);
+        let (back_generation, middle_generation) = (Self::generation(&back), Self::generation(&middle));
+
+        for x in chunk_x.clone() {
+            for y in chunk_y.clone() {
+                let rebuild = match self.chunks.get(&(x, y)) {
+                    Some(chunk) => !chunk.complete && (chunk.back != back_generation || chunk.middle != middle_generation),
+                    None => true,
+                };
+                if rebuild {
+                    let sets = Self::tile_sets(map, x, y);
+                    if !self.chunks.contains_key(&(x, y)) {
+                        Self::load(cache, &sets);
+                    }
+                    let chunk = Self::bake(ctx, &back, &middle, &sets, x, y);
+                    self.chunks.insert((x, y), chunk);
+                }
+                if let Some(chunk) = self.chunks.get(&(x, y)) {
+                    let (origin_x, origin_y) = Self::origin(x, y);
+                    let p = map.camera().world_to_screen(origin_x, origin_y);
+                    let zoom = map.camera().zoom() as f32;
+                    canvas.draw(&chunk.image, DrawParam::default().dest(vec2(p.x, p.y)).scale(vec2(zoom, zoom)));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use ggez::{Context, GameResult};
+use ggez::glam::vec2;
+use ggez::graphics::{Canvas, Color, DrawMode, DrawParam, Mesh, MeshBuilder, Quad, Rect, Text};
+use crate::cache::CacheKey;
+use crate::draw::camera;
+use crate::draw::map::MapDraw;
+
+const GRID_COLOR: Color = Color::new(1., 1., 1., 0.25);
+const BLOCK_COLOR: Color = Color::new(1., 0., 0., 0.3);
+const DOOR_CLOSE_COLOR: Color = Color::new(0., 0.4, 1., 0.5);
+const DOOR_OPEN_COLOR: Color = Color::new(0., 1., 0.4, 0.5);
+const LIGHT_COLOR: Color = Color::new(1., 1., 0., 0.8);
+const ANIMATION_COLOR: Color = Color::new(1., 0., 1., 0.8);
+const HOVER_COLOR: Color = Color::new(1., 1., 1., 0.9);
+/// 坐标只标在行列都是该数倍数的格子上, 其他格子看悬停面板
+const COORDINATE_STEP: i32 = 5;
+
+/// 地图调试层: 网格, 坐标, 阻挡, 门, 光源, 动画物件, 以及鼠标所在格子的原始数据
+#[derive(Default)]
+pub struct MapDebugOverlay {
+    enabled: bool,
+    show_coordinates: bool,
+    mouse: (f32, f32),
+}
+
+impl MapDebugOverlay {
+    pub fn new() -> Self {
+        Self { enabled: false, show_coordinates: true, mouse: (0., 0.) }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+    }
+
+    pub fn toggle_coordinates(&mut self) {
+        self.show_coordinates = !self.show_coordinates;
+    }
+
+    pub fn set_mouse(&mut self, screen_x: f32, screen_y: f32) {
+        self.mouse = (screen_x, screen_y);
+    }
+
+    pub fn draw(&self, ctx: &mut Context, canvas: &mut Canvas, map: &MapDraw) -> GameResult {
+        if !self.enabled {
+            return Ok(());
+        }
+        let camera = map.camera();
+        let data = map.map_data();
+        let (start_x, start_y, end_x, end_y) = map.ground_window();
+        let (tile_width, tile_height) = (camera::TILE_WIDTH, camera::TILE_HEIGHT);
+
+        let mut builder = MeshBuilder::new();
+        for x in start_x..=end_x {
+            for y in start_y..=end_y {
+                let tile = match data.tile(x, y) {
+                    Some(tile) => tile,
+                    None => continue,
+                };
+                let p = camera::tile_to_world(x, y);
+                let rect = Rect::new(p.x, p.y, tile_width, tile_height);
+                builder.rectangle(DrawMode::stroke(1.), rect, GRID_COLOR)?;
+                if tile.door_index() > 0 {
+                    let color = if tile.is_door_open() { DOOR_OPEN_COLOR } else { DOOR_CLOSE_COLOR };
+                    builder.rectangle(DrawMode::fill(), rect, color)?;
+                } else if !tile.can_walk() {
+                    builder.rectangle(DrawMode::fill(), rect, BLOCK_COLOR)?;
+                }
+                if tile.light > 0 {
+                    builder.circle(DrawMode::fill(), vec2(p.x + tile_width / 2., p.y + tile_height / 2.), 4., 0.5, LIGHT_COLOR)?;
+                }
+                if tile.frame & 0x7F > 0 {
+                    builder.rectangle(DrawMode::stroke(2.), Rect::new(p.x + 4., p.y + 4., tile_width - 8., tile_height - 8.), ANIMATION_COLOR)?;
+                }
+            }
+        }
+        let (hover_x, hover_y) = map.screen_to_tile(self.mouse.0, self.mouse.1);
+        let p = camera::tile_to_world(hover_x, hover_y);
+        builder.rectangle(DrawMode::stroke(2.), Rect::new(p.x, p.y, tile_width, tile_height), HOVER_COLOR)?;
+        let mesh = Mesh::from_data(ctx, builder.build());
+        canvas.draw(&mesh, camera.draw_param());
+
+        if self.show_coordinates {
+            let first = |start: i32| start + (COORDINATE_STEP - start.rem_euclid(COORDINATE_STEP)) % COORDINATE_STEP;
+            for x in (first(start_x)..=end_x).step_by(COORDINATE_STEP as usize) {
+                for y in (first(start_y)..=end_y).step_by(COORDINATE_STEP as usize) {
+                    if data.tile(x, y).is_none() {
+                        continue;
+                    }
+                    let p = camera.tile_to_screen(x, y);
+                    let mut text = Text::new(format!("{},{}", x, y));
+                    text.set_scale(10.);
+                    canvas.draw(&text, DrawParam::default().dest(vec2(p.x + 2., p.y + 2.)).color(GRID_COLOR));
+                }
+            }
+        }
+
+        self.draw_hover_panel(canvas, map, hover_x, hover_y);
+        Ok(())
+    }
+
+    fn draw_hover_panel(&self, canvas: &mut Canvas, map: &MapDraw, tile_x: i32, tile_y: i32) {
+        let set = match map.build_tile_set(tile_x, tile_y, true) {
+            Some(set) => set,
+            None => return,
+        };
+        let key_info = |name: &str, key: &CacheKey| format!(
+            "{}: data_key={} meta_key={} file={}/{} index={}",
+            name, key.get_data_key(), key.get_meta_key(), key.get_file_id(), key.get_file_number(), key.get_file_index());
+        let t = &set.tile;
+        let lines = [
+            format!("tile: ({}, {})", tile_x, tile_y),
+            format!("back: {:#06x} back_idx: {}", t.back, t.back_idx),
+            format!("middle: {:#06x} middle_idx: {}", t.middle, t.middle_idx),
+            format!("objects: {:#06x} objects_idx: {}", t.objects, t.objects_idx),
+            format!("door_idx: {:#04x} door_offset: {:#04x}", t.door_idx, t.door_offset),
+            format!("frame: {:#04x} tick: {} light: {}", t.frame, t.tick, t.light),
+            format!("walk: {}", t.can_walk()),
+            key_info("back", &set.back_key),
+            key_info("middle", &set.middle_key),
+            key_info("object", &set.object_key),
+        ];
+        let mut text = Text::new(lines.join("\n"));
+        text.set_scale(14.);
+        let (x, y) = (self.mouse.0 + 16., self.mouse.1 + 16.);
+        canvas.draw(&Quad, DrawParam::default().dest(vec2(x - 4., y - 4.)).scale(vec2(460., lines.len() as f32 * 16. + 8.)).color(Color::new(0., 0., 0., 0.7)));
+        canvas.draw(&text, DrawParam::default().dest(vec2(x, y)).color(Color::WHITE));
+    }
+}
@@ -0,0 +1,65 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use tracing::info;
+use crate::asset::{self, EffectInfo};
+use crate::cache::{ArchiveNames, CacheKey, ImageCache};
+
+pub const EFFECT_TABLE: &str = "config/effects.txt";
+/// 效果图集的 data_id, data_number 为效果编号
+const EFFECT_DATA_ID: u32 = 220;
+
+/// 魔法效果表, 按效果编号查找, 第一次播放时整段加载
+#[derive(Debug, Clone, Default)]
+pub struct EffectTable {
+    infos: HashMap<u32, EffectInfo>,
+    files: ArchiveNames,
+    loaded: HashSet<u32>,
+}
+
+impl EffectTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(base_dir: &Path) -> Self {
+        let mut result = Self::new();
+        asset::read_effect_info(base_dir.join(EFFECT_TABLE)).into_iter().for_each(|info| result.insert(info));
+        info!("加载效果表: {} 共 {} 个", EFFECT_TABLE, result.infos.len());
+        result
+    }
+
+    pub fn insert(&mut self, info: EffectInfo) {
+        self.files.insert(&info.file);
+        self.infos.insert(info.id, info);
+    }
+
+    pub fn get(&self, id: u32) -> Option<&EffectInfo> {
+        self.infos.get(&id)
+    }
+
+    /// 把图片库名注册到 ImageCache, 加载和取图片之前调用
+    pub fn register(&mut self, cache: &mut ImageCache) {
+        self.files.register(cache);
+    }
+
+    /// 第一次用到某个效果时加载所有方向的帧
+    pub fn load_effect(&mut self, cache: &mut ImageCache, id: u32) {
+        let info = match self.infos.get(&id) {
+            Some(info) => info,
+            None => return,
+        };
+        if !self.loaded.insert(id) {
+            return;
+        }
+        if let Some(file_id) = self.files.id(&info.file) {
+            cache.load_key(CacheKey::from(EFFECT_DATA_ID, id, 2, info.frames.count(), file_id, 1, info.frames.start));
+        }
+    }
+
+    /// 某个效果一帧的图片, direction 为 0 ~ directions-1
+    pub fn key(&self, id: u32, direction: u8, frame: u32) -> Option<CacheKey> {
+        let info = self.infos.get(&id)?;
+        let file_id = self.files.id(&info.file)?;
+        Some(CacheKey::from(EFFECT_DATA_ID, id, 2, 1, file_id, 1, info.frames.index(direction, frame)))
+    }
+}
@@ -0,0 +1,48 @@
+use ggez::Context;
+use ggez::glam::vec2;
+use ggez::graphics::{Canvas, Color, DrawParam, Rect};
+use crate::cache::{CacheKey, ImageCache};
+
+/// 界面图集使用的 data_id, data_number 为文件编号
+pub const UI_DATA_ID: u32 = 200;
+pub const PRGUSE_FILE_ID: u32 = 5;
+pub const CHRSEL_FILE_ID: u32 = 7;
+
+pub fn frame_key(file_id: u32, index: u32) -> CacheKey {
+    CacheKey::from(UI_DATA_ID, file_id, 2, 1, file_id, 1, index)
+}
+
+/// 图片已加载时返回宽高
+pub fn frame_size(ctx: &mut Context, cache: &mut ImageCache, key: CacheKey) -> Option<(f32, f32)> {
+    let value = cache.get(ctx, &key.get_data_key())?;
+    let meta = value.meta(key.get_meta_key())?;
+    Some((meta.width as f32, meta.height as f32))
+}
+
+/// 以左上角为 (x, y) 绘制界面图片, 忽略图片自带的偏移, 未加载时返回 false
+pub fn draw_frame(ctx: &mut Context, canvas: &mut Canvas, cache: &mut ImageCache, key: CacheKey, x: f32, y: f32, color: Color) -> bool {
+    let value = match cache.get(ctx, &key.get_data_key()) {
+        Some(value) => value,
+        None => return false,
+    };
+    let meta = match value.meta(key.get_meta_key()) {
+        Some(meta) if meta.width > 0 && meta.height > 0 => meta,
+        _ => return false,
+    };
+    let image = value.image();
+    let (image_width, image_height) = (image.width() as f32, image.height() as f32);
+    canvas.draw(&image, DrawParam::default()
+        .src(Rect::new(meta.src_x / image_width, meta.src_y / image_height, meta.width as f32 / image_width, meta.height as f32 / image_height))
+        .dest(vec2(x, y))
+        .color(color));
+    true
+}
+
+/// 按图片自带的偏移绘制, 用于人物等以所在格子为原点的图片
+pub fn draw_sprite(ctx: &mut Context, canvas: &mut Canvas, cache: &mut ImageCache, key: CacheKey, x: f32, y: f32, color: Color) -> bool {
+    let offset = match cache.get(ctx, &key.get_data_key()).and_then(|v| v.meta(key.get_meta_key()).map(|m| (m.offset_x, m.offset_y))) {
+        Some(offset) => offset,
+        None => return false,
+    };
+    draw_frame(ctx, canvas, cache, key, x + offset.0, y + offset.1, color)
+}
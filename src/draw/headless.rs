@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use ggez::{GameError, GameResult};
+use image::RgbaImage;
+use moka::sync::Cache;
+use tracing::info;
+use crate::asset;
+use crate::asset::{ImageData, MapData};
+use crate::cache::{self, CacheKey};
+use crate::draw::camera;
+use crate::draw::map::{self, DEFAULT_OBJECT_EXTENT};
+use crate::easing::Point2;
+
+/// 同步读取资源文件的图片, 不依赖窗口和 GPU
+pub struct ArchiveSet {
+    data_dir: PathBuf,
+    names: Cache<u32, String>,
+    index: HashMap<u32, Vec<u32>>,
+    images: HashMap<CacheKey, ImageData>,
+}
+
+impl ArchiveSet {
+    pub fn new(data_dir: PathBuf) -> Self {
+        Self {
+            data_dir,
+            names: cache::default_names(),
+            index: HashMap::new(),
+            images: HashMap::new(),
+        }
+    }
+
+    pub fn add_name(&mut self, key: u32, name: String) {
+        self.names.insert(key, name);
+    }
+
+    pub fn get(&mut self, key: CacheKey) -> &ImageData {
+        let (index, names, data_dir) = (&mut self.index, &self.names, &self.data_dir);
+        self.images.entry(key).or_insert_with(|| cache::load_image0(index, key, names, data_dir))
+    }
+}
+
+/// CPU 合成地图区域, 与 MapDraw 使用相同的偏移规则
+pub struct MapRenderer<'a> {
+    map: &'a MapData,
+    archives: &'a mut ArchiveSet,
+    data_id: u32,
+    data_number: u32,
+}
+
+impl<'a> MapRenderer<'a> {
+    pub fn new(map: &'a MapData, archives: &'a mut ArchiveSet, data_id: u32, data_number: u32) -> Self {
+        Self { map, archives, data_id, data_number }
+    }
+
+    /// 渲染以 (tile_x, tile_y) 为左上角, width x height 个格子的区域
+    pub fn render(&mut self, tile_x: i32, tile_y: i32, width: i32, height: i32) -> RgbaImage {
+        let mut target = RgbaImage::new((width as f32 * camera::TILE_WIDTH) as u32, (height as f32 * camera::TILE_HEIGHT) as u32);
+        let origin = camera::tile_to_world(tile_x, tile_y);
+        let (margin_x, margin_y) = map::object_margin(DEFAULT_OBJECT_EXTENT);
+
+        // 大地砖从偶数格开始, 向左上多取一格
+        let ground = self.cells(tile_x - 1, tile_y - 1, width + 1, height + 1);
+        for (x, y, back, _, _) in ground.iter() {
+            if x & 0x1 != 1 && y & 0x1 != 1 && (self.tile_field(*x, *y, |t| t.back) & 0x7FFF) > 0 {
+                self.blit(&mut target, *back, *x, *y, origin, false);
+            }
+        }
+        for (x, y, _, middle, _) in ground.iter() {
+            if (self.tile_field(*x, *y, |t| t.middle) & 0x7FFF) > 0 {
+                self.blit(&mut target, *middle, *x, *y, origin, false);
+            }
+        }
+
+        // 物件按行排序, 贴地物件先画
+        let objects = self.cells(tile_x - 1 - margin_x, tile_y - 1, width + 1 + margin_x, height + 1 + margin_y)
+            .into_iter()
+            .filter(|(x, y, _, _, _)| (self.tile_field(*x, *y, |t| t.objects) & 0x7FFF) > 0)
+            .collect::<Vec<_>>();
+        let (flat, objects): (Vec<_>, Vec<_>) = objects.into_iter().partition(|(_, _, _, _, object)| {
+            let data = self.archives.get(*object);
+            data.width == 48 && data.height == 32
+        });
+        for (x, y, _, _, object) in flat.iter().chain(objects.iter()) {
+            self.blit(&mut target, *object, *x, *y, origin, true);
+        }
+        target
+    }
+
+    /// 整张地图的缩略图, 每个格子取大地砖和小地砖的平均色, 不可通行的格子压暗
+    pub fn overview(&mut self, cell_width: u32, cell_height: u32) -> RgbaImage {
+        let mut target = RgbaImage::new(self.map.width * cell_width, self.map.height * cell_height);
+        let mut colors: HashMap<CacheKey, Option<[u8; 4]>> = HashMap::new();
+        for y in 0..self.map.height as i32 {
+            for x in 0..self.map.width as i32 {
+                // 大地砖 96x64, 取所在偶数格的图片
+                let back = self.map.tile(x & !0x1, y & !0x1)
+                    .filter(|t| (t.back & 0x7FFF) > 0)
+                    .map(|t| map::tile_keys(self.data_id, self.data_number, t).0);
+                let middle = self.map.tile(x, y)
+                    .filter(|t| (t.middle & 0x7FFF) > 0)
+                    .map(|t| map::tile_keys(self.data_id, self.data_number, t).1);
+                let mut color = [0u8, 0, 0, 255];
+                for key in back.into_iter().chain(middle) {
+                    let average = *colors.entry(key).or_insert_with(|| average_color(self.archives.get(key)));
+                    if let Some(c) = average {
+                        color = [c[0], c[1], c[2], 255];
+                    }
+                }
+                if !self.map.can_walk(x, y) {
+                    color = [color[0] / 2, color[1] / 2, color[2] / 2, 255];
+                }
+                for j in 0..cell_height {
+                    for i in 0..cell_width {
+                        target.put_pixel(x as u32 * cell_width + i, y as u32 * cell_height + j, image::Rgba(color));
+                    }
+                }
+            }
+        }
+        target
+    }
+
+    /// 区域内格子的三层 key, 按行优先排列
+    fn cells(&self, start_x: i32, start_y: i32, width: i32, height: i32) -> Vec<(i32, i32, CacheKey, CacheKey, CacheKey)> {
+        let mut cells = Vec::new();
+        for y in start_y..start_y + height {
+            for x in start_x..start_x + width {
+                if let Some(tile) = self.map.tile(x, y) {
+                    let (back, middle, object) = map::tile_keys(self.data_id, self.data_number, tile);
+                    cells.push((x, y, back, middle, object));
+                }
+            }
+        }
+        cells
+    }
+
+    fn tile_field(&self, x: i32, y: i32, f: impl Fn(&asset::Tile) -> u16) -> u16 {
+        self.map.tile(x, y).map_or(0, f)
+    }
+
+    fn blit(&mut self, target: &mut RgbaImage, key: CacheKey, tile_x: i32, tile_y: i32, origin: Point2, bottom: bool) {
+        let data = self.archives.get(key);
+        if data.bytes.is_empty() || data.width == 0 || data.height == 0 {
+            return;
+        }
+        let world = camera::tile_to_world(tile_x, tile_y);
+        let x = (world.x + data.offset_x - origin.x) as i32;
+        let y = (world.y + data.offset_y - origin.y - if bottom { data.height as f32 } else { 0. }) as i32;
+        blend(target, data, x, y);
+    }
+}
+
+/// 不透明像素的平均色
+fn average_color(data: &ImageData) -> Option<[u8; 4]> {
+    let (mut r, mut g, mut b, mut n) = (0u64, 0u64, 0u64, 0u64);
+    data.bytes.chunks_exact(4).filter(|p| p[3] > 0).for_each(|p| {
+        r += p[0] as u64;
+        g += p[1] as u64;
+        b += p[2] as u64;
+        n += 1;
+    });
+    if n == 0 {
+        return None;
+    }
+    Some([(r / n) as u8, (g / n) as u8, (b / n) as u8, 255])
+}
+
+/// 按 alpha 混合把图片画到目标上, 超出部分裁掉
+fn blend(target: &mut RgbaImage, data: &ImageData, x: i32, y: i32) {
+    let (target_width, target_height) = (target.width() as i32, target.height() as i32);
+    for j in 0..data.height as i32 {
+        let ty = y + j;
+        if ty < 0 || ty >= target_height {
+            continue;
+        }
+        for i in 0..data.width as i32 {
+            let tx = x + i;
+            if tx < 0 || tx >= target_width {
+                continue;
+            }
+            let p = ((j * data.width as i32 + i) * 4) as usize;
+            let src = &data.bytes[p..p + 4];
+            let alpha = src[3] as u32;
+            if alpha == 0 {
+                continue;
+            }
+            let dst = target.get_pixel_mut(tx as u32, ty as u32);
+            for c in 0..3 {
+                dst[c] = ((src[c] as u32 * alpha + dst[c] as u32 * (255 - alpha)) / 255) as u8;
+            }
+            dst[3] = (alpha + dst[3] as u32 * (255 - alpha) / 255) as u8;
+        }
+    }
+}
+
+/// 把资源文件中的单张图片转成 RGBA 图
+pub fn to_rgba(data: &ImageData) -> Option<RgbaImage> {
+    if data.width == 0 || data.height == 0 || data.bytes.is_empty() {
+        return None;
+    }
+    RgbaImage::from_raw(data.width, data.height, data.bytes.to_vec())
+}
+
+pub fn write_png<P: AsRef<Path>>(image: &RgbaImage, path: P) -> GameResult {
+    image.save_with_format(path.as_ref(), image::ImageFormat::Png)
+        .map_err(|e| GameError::CustomError(format!("写入图片失败: {:?}, {}", path.as_ref(), e)))
+}
+
+/// 命令行入口: render <地图> <data_id> <data_number> <x> <y> <宽> <高> <输出.png>
+pub fn run(base_dir: &Path, args: &[String]) -> GameResult {
+    if args.len() < 8 {
+        return Err(GameError::CustomError(String::from("用法: render <地图> <data_id> <data_number> <x> <y> <宽> <高> <输出.png>")));
+    }
+    let number = |i: usize| args[i].parse::<i32>().map_err(|_| GameError::CustomError(format!("参数不是数字: {}", args[i])));
+    let (data_id, data_number) = (number(1)? as u32, number(2)? as u32);
+    let (x, y, width, height) = (number(3)?, number(4)?, number(5)?, number(6)?);
+    let map = asset::read_map_file(base_dir.join("map").join(&args[0]).with_extension("map"))
+        .ok_or_else(|| GameError::CustomError(format!("未找到地图: {}", args[0])))?;
+    let mut archives = ArchiveSet::new(base_dir.join("data"));
+    let image = MapRenderer::new(&map, &mut archives, data_id, data_number).render(x, y, width, height);
+    write_png(&image, &args[7])?;
+    info!("地图 {} 已输出: {}", args[0], args[7]);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asset::Tile;
+
+    const DATA_ID: u32 = 1;
+    const DATA_NUMBER: u32 = 1;
+
+    fn tile(back: u16, middle: u16, objects: u16) -> Tile {
+        Tile { back, middle, objects, door_idx: 0, door_offset: 0, frame: 0, tick: 0, light: 0, objects_idx: 0, back_idx: 0, middle_idx: 0 }
+    }
+
+    fn solid(width: u32, height: u32, color: [u8; 4]) -> ImageData {
+        let bytes = (0..width * height).flat_map(|_| color).collect::<Vec<u8>>();
+        ImageData { width, height, offset_x: 0., offset_y: 0., bytes: bytes.into() }
+    }
+
+    #[test]
+    fn golden_render() {
+        let (red, green, blue) = ([255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]);
+        // 格子按列存放: (0,0) 大地砖, (0,1) 物件, (1,1) 小地砖
+        let map = MapData::new(2, 2, vec![tile(1, 0, 0), tile(0, 0, 1), tile(0, 0, 0), tile(0, 1, 0)]);
+        let mut archives = ArchiveSet::new(PathBuf::new());
+        let (back, middle, object) = map::tile_keys(DATA_ID, DATA_NUMBER, &tile(1, 1, 1));
+        archives.images.insert(back, solid(96, 64, red));
+        archives.images.insert(middle, solid(48, 32, green));
+        // 物件以格子顶边为底边, 向上超出区域的部分被裁掉
+        archives.images.insert(object, solid(48, 48, blue));
+
+        let image = MapRenderer::new(&map, &mut archives, DATA_ID, DATA_NUMBER).render(0, 0, 2, 2);
+        let expected = RgbaImage::from_fn(96, 64, |x, y| image::Rgba(match (x < 48, y < 32) {
+            (true, true) => blue,
+            (false, false) => green,
+            _ => red,
+        }));
+        assert_eq!(image, expected);
+    }
+}
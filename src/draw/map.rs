@@ -1,28 +1,54 @@
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::path::{Path, PathBuf};
-use std::time::{Duration, Instant};
+use std::sync::Arc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
 use ggez::Context;
 use ggez::glam::{vec2};
 use ggez::graphics::{Canvas, Color, DrawMode, DrawParam, InstanceArray, Mesh, Rect, ScreenImage, StrokeOptions, Text};
 use tracing::error;
 use crate::{asset};
-use crate::asset::Tile;
-use crate::cache::{CacheKey, ImageCache};
+use crate::asset::{MapData, ObjectShadow, Tile};
+use crate::cache::{self, CacheKey, ImageCache, ImageMeta, ImageValue};
+use crate::draw::blend::SpriteStyle;
+use crate::draw::camera::{self, Camera};
+use crate::draw::chunk::GroundChunks;
+use crate::draw::debug::MapDebugOverlay;
+use crate::easing::Point2;
+use crate::draw::order::{self, DepthLayer, RenderQueue, Sprite};
 
 #[derive(Debug)]
 pub struct MapTileSet {
-    layer: i32,
-    even: bool,
-    tile: Tile,
-    x: f32,
-    y: f32,
-    back_key: CacheKey,
-    middle_key: CacheKey,
-    object_key: CacheKey,
+    pub(crate) layer: i64,
+    pub(crate) even: bool,
+    pub(crate) ground: bool,
+    pub(crate) tile: Tile,
+    pub(crate) x: f32,
+    pub(crate) y: f32,
+    pub(crate) back_key: CacheKey,
+    pub(crate) middle_key: CacheKey,
+    pub(crate) object_key: CacheKey,
+}
+
+impl MapTileSet {
+    /// 大地砖只在偶数格上
+    pub fn has_back(&self) -> bool {
+        self.even && (self.tile.back & 0x7FFF) > 0
+    }
+
+    pub fn has_middle(&self) -> bool {
+        (self.tile.middle & 0x7FFF) > 0
+    }
+
+    pub fn has_object(&self) -> bool {
+        (self.tile.objects & 0x7FFF) > 0
+    }
 }
 
 pub struct MapDraw {
     map_dir: PathBuf,
+    data_dir: PathBuf,
     map_name: String,
     data_id: u32,
     data_number: u32,
@@ -32,18 +58,86 @@ pub struct MapDraw {
     tile_height: i32,
     current_tile_x: i32,
     current_tile_y: i32,
-    absolute_offset_x: f32,
-    absolute_offset_y: f32,
-    tiles: Vec<Tile>,
+    camera: Camera,
+    map_data: MapData,
     reload: bool,
     current_tile_set: Vec<MapTileSet>,
+    render_queue: RenderQueue,
+    object_extents: HashMap<String, (u32, u32)>,
+    /// 后台线程读取物件图片头的结果
+    extent_sender: Sender<(String, (u32, u32))>,
+    extent_receiver: Receiver<(String, (u32, u32))>,
+    object_shadows: Vec<ObjectShadow>,
+    window: (i32, i32, i32, i32),
+    ground_window: (i32, i32, i32, i32),
+    ground_chunks: GroundChunks,
+    debug: MapDebugOverlay,
+    last_position: Point2,
+    velocity: Point2,
+    prefetch_direction: (i32, i32),
+}
+
+pub const OBJECT_SHADOW_TABLE: &str = "config/object_shadows.txt";
+const OBJECTS_FILE_ID: u32 = 3;
+/// 物件尺寸读取失败时使用的保守估计, 与原先固定多取 12 行一致
+pub(crate) const DEFAULT_OBJECT_EXTENT: (u32, u32) = (144, 384);
+/// 跑步一步两格, 预取至少覆盖一步
+const PREFETCH_MIN_TILES: i32 = 2;
+const PREFETCH_MAX_TILES: i32 = 8;
+/// 低于该速度(像素/秒)视为静止
+const PREFETCH_MIN_SPEED: f32 = 10.;
+
+/// 格子三层图片的 key: 大地砖, 小地砖, 物件(开门时加上门的帧偏移)
+pub fn tile_keys(data_id: u32, data_number: u32, tile: &Tile) -> (CacheKey, CacheKey, CacheKey) {
+    let back_idx = tile.back as u32 & 0x7FFF;
+    let middle_idx = tile.middle as u32 & 0x7FFF;
+    let object_idx = (tile.objects as u32 & 0x7FFF) + tile.door_frame_offset() as u32;
+    let back_idx = if back_idx > 0 { back_idx - 1 } else { 0 };
+    let middle_idx = if middle_idx > 0 { middle_idx - 1 } else { 0 };
+    let object_idx = if object_idx > 0 { object_idx - 1 } else { 0 };
+    (
+        CacheKey::from(data_id, data_number, 2, 1, 1, tile.back_idx as u32 + 1, back_idx),
+        CacheKey::from(data_id, data_number + 1, 2, 1, 2, tile.middle_idx as u32 + 1, middle_idx),
+        CacheKey::from(data_id, data_number + 2, 2, 1, 3, tile.objects_idx as u32 + 1, object_idx),
+    )
+}
+
+/// 物件超出可见区域需要多取的列数(左侧)和行数(下方)
+pub fn object_margin(extent: (u32, u32)) -> (i32, i32) {
+    let (width, height) = extent;
+    let margin_x = ((width as f32 - camera::TILE_WIDTH) / camera::TILE_WIDTH).ceil().max(0.) as i32;
+    let margin_y = (height as f32 / camera::TILE_HEIGHT).ceil() as i32;
+    (margin_x, margin_y)
+}
+
+/// 读取物件的图片头, 返回最大宽高, 读不到任何图片时为空
+fn read_object_extent(data_dir: &Path, indices: HashMap<u32, Vec<u32>>) -> Option<(u32, u32)> {
+    let name = cache::default_names().get(&OBJECTS_FILE_ID)?;
+    let mut extent: Option<(u32, u32)> = None;
+    for (file_number, mut objects) in indices {
+        objects.sort_unstable();
+        objects.dedup();
+        let index = cache::get_file_name(&data_dir, &name, file_number, 2).map(asset::read_wzx).unwrap_or_default();
+        let offsets = objects.iter().filter_map(|i| index.get(*i as usize).copied()).collect::<Vec<u32>>();
+        let path = match cache::get_file_name(&data_dir, &name, file_number, 0) {
+            Some(path) => path,
+            None => continue,
+        };
+        for (width, height) in asset::read_image_sizes(path, &offsets) {
+            let (w, h) = extent.unwrap_or((0, 0));
+            extent = Some((w.max(width), h.max(height)));
+        }
+    }
+    extent
 }
 
 impl MapDraw {
 
     pub fn new(base_dir: &Path, data_id: u32, data_number: u32, name: &str, window_width: f32, window_height: f32) -> Self {
+        let (extent_sender, extent_receiver) = mpsc::channel();
         let mut this = Self {
             map_dir: base_dir.join("map"),
+            data_dir: base_dir.join("data"),
             map_name: String::from(name),
             data_id,
             data_number,
@@ -53,11 +147,22 @@ impl MapDraw {
             tile_height: 0,
             current_tile_x: 0,
             current_tile_y: 0,
-            absolute_offset_x: 0.,
-            absolute_offset_y: 0.,
-            tiles: Vec::new(),
+            camera: Camera::new(window_width, window_height),
+            map_data: MapData::default(),
             reload: true,
             current_tile_set: Vec::new(),
+            render_queue: RenderQueue::new(),
+            object_extents: HashMap::new(),
+            extent_sender,
+            extent_receiver,
+            object_shadows: asset::read_object_shadows(base_dir.join(OBJECT_SHADOW_TABLE)),
+            window: (0, 0, 0, 0),
+            ground_window: (0, 0, 0, 0),
+            ground_chunks: GroundChunks::new(),
+            debug: MapDebugOverlay::new(),
+            last_position: Point2::default(),
+            velocity: Point2::default(),
+            prefetch_direction: (0, 0),
         };
         this.reload_map_data();
         this
@@ -67,11 +172,32 @@ impl MapDraw {
         if let Some(data) = asset::read_map_file(self.map_dir.join(&self.map_name).with_extension("map")) {
             self.tile_width = data.width as i32;
             self.tile_height = data.height as i32;
-            self.tiles = data.tiles;
+            self.map_data = data;
         } else {
+            self.map_data = MapData::default();
             error!("未找到地图: {}", self.map_name);
         }
+        self.ground_chunks.clear();
+        if !self.object_extents.contains_key(&self.map_name) {
+            self.scan_object_extent();
+        }
+    }
 
+    /// 在后台线程读取地图用到的所有物件的图片头, 得到最大宽高后发回, 读完之前用保守估计
+    fn scan_object_extent(&self) {
+        let mut indices: HashMap<u32, Vec<u32>> = HashMap::new();
+        for tile in self.map_data.tiles.iter() {
+            let object_idx = tile.objects as u32 & 0x7FFF;
+            if object_idx > 0 {
+                indices.entry(tile.objects_idx as u32 + 1).or_default().push(object_idx - 1);
+            }
+        }
+        let (map_name, data_dir, sender) = (self.map_name.clone(), self.data_dir.clone(), self.extent_sender.clone());
+        thread::spawn(move || {
+            if let Some(extent) = read_object_extent(&data_dir, indices) {
+                let _ = sender.send((map_name, extent));
+            }
+        });
     }
 
     pub fn reload_map(&mut self, data_id: u32, data_number: u32, name: &str, tile_x: i32, tile_y: i32, rel_offset_x: i32, rel_offset_y: i32) {
@@ -84,24 +210,44 @@ impl MapDraw {
     }
 
     pub fn jump_by_tile(&mut self, tile_x: i32, tile_y: i32, rel_offset_x: i32, rel_offset_y: i32) {
+        let tile_x = tile_x.clamp(0, (self.tile_width - 1).max(0));
+        let tile_y = tile_y.clamp(0, (self.tile_height - 1).max(0));
+        let center = camera::tile_center(tile_x, tile_y);
+        self.camera.jump_to(center.x + rel_offset_x as f32, center.y + rel_offset_y as f32);
+        // 跳转不计入移动速度
+        self.last_position = self.camera.position();
+        self.velocity = Point2::default();
         self.current_tile_x = tile_x;
         self.current_tile_y = tile_y;
-        if tile_x > self.tile_width {
-            self.current_tile_x = self.tile_width;
-        }
-        if tile_y > self.tile_height {
-            self.current_tile_y = self.tile_height;
-        }
-        self.absolute_offset_x = ((self.current_tile_x - 1) * 48 + rel_offset_x) as f32;
-        self.absolute_offset_y = ((self.current_tile_y - 1) * 32 + rel_offset_y) as f32;
         self.reload = true;
     }
 
     pub fn move_by_pixel(&mut self, rel_offset_x: f32, rel_offset_y: f32) {
-        self.absolute_offset_x += rel_offset_x;
-        self.absolute_offset_y += rel_offset_y;
-        let tile_x = (self.absolute_offset_x / 48.) as i32;
-        let tile_y = (self.absolute_offset_y / 32.) as i32;
+        let position = self.camera.position();
+        self.camera.jump_to(position.x + rel_offset_x, position.y + rel_offset_y);
+        self.update_current_tile();
+    }
+
+    /// 摄像机平滑跟随世界坐标(像素)
+    pub fn follow(&mut self, x: f32, y: f32) {
+        self.camera.follow(x, y);
+    }
+
+    pub fn update_move_pixel(&mut self, time: f64) {
+        if self.camera.update(time) {
+            self.update_current_tile();
+        }
+        let position = self.camera.position();
+        if time > 0. {
+            let vx = (position.x - self.last_position.x) / time as f32;
+            let vy = (position.y - self.last_position.y) / time as f32;
+            self.velocity = Point2::new(self.velocity.x * 0.8 + vx * 0.2, self.velocity.y * 0.8 + vy * 0.2);
+        }
+        self.last_position = position;
+    }
+
+    fn update_current_tile(&mut self) {
+        let (tile_x, tile_y) = self.camera.current_tile();
         if tile_x != self.current_tile_x || tile_y != self.current_tile_y {
             self.current_tile_x = tile_x;
             self.current_tile_y = tile_y;
@@ -109,131 +255,268 @@ impl MapDraw {
         }
     }
 
-    pub fn update_move_pixel(&mut self, time: f64) {
+    /// 窗口大小变化后重新计算可见格子
+    pub fn resize(&mut self, window_width: f32, window_height: f32) {
+        let (width, height) = self.camera.window_size();
+        if width != window_width || height != window_height {
+            self.camera.set_window_size(window_width, window_height);
+            self.reload = true;
+        }
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub fn set_zoom(&mut self, zoom: u32) {
+        self.camera.set_zoom(zoom);
+        self.reload = true;
+    }
+
+    pub fn current_tile(&self) -> (i32, i32) {
+        (self.current_tile_x, self.current_tile_y)
+    }
+
+    pub fn screen_to_tile(&self, screen_x: f32, screen_y: f32) -> (i32, i32) {
+        self.camera.screen_to_tile(screen_x, screen_y)
+    }
+
+    pub fn map_data(&self) -> &MapData {
+        &self.map_data
+    }
+
+    pub fn can_walk(&self, tile_x: i32, tile_y: i32) -> bool {
+        self.map_data.can_walk(tile_x, tile_y)
+    }
+
+    pub fn set_door_open(&mut self, door_idx: u8, open: bool) {
+        if self.map_data.set_door_open(door_idx, open) {
+            self.reload = true;
+        } else {
+            error!("未找到门: {}, 地图: {}", door_idx, self.map_name);
+        }
+    }
+
+    pub fn is_door_open(&self, door_idx: u8) -> bool {
+        self.map_data.is_door_open(door_idx)
+    }
 
+    pub(crate) fn build_tile_set(&self, tile_x: i32, tile_y: i32, ground: bool) -> Option<MapTileSet> {
+        let tile = self.map_data.tile(tile_x, tile_y)?;
+        let even = tile_x & 0x1 != 1 && tile_y & 0x1 != 1;
+        let (back_key, middle_key, object_key) = tile_keys(self.data_id, self.data_number, tile);
+
+        Some(MapTileSet {
+            layer: order::depth(tile_y, DepthLayer::Object, tile_x),
+            even,
+            ground,
+            tile: tile.clone(),
+            x: tile_x as f32 * camera::TILE_WIDTH,
+            y: tile_y as f32 * camera::TILE_HEIGHT,
+            back_key,
+            middle_key,
+            object_key,
+        })
+    }
+
+    /// 按移动方向预取可见窗口外的一圈格子, 掉头时取消尚未处理的预取
+    fn prefetch(&mut self, cache: &mut ImageCache, window_changed: bool) {
+        let sign = |v: f32| if v > PREFETCH_MIN_SPEED { 1 } else if v < -PREFETCH_MIN_SPEED { -1 } else { 0 };
+        let direction = (sign(self.velocity.x), sign(self.velocity.y));
+        let (last_x, last_y) = self.prefetch_direction;
+        if direction.0 * last_x < 0 || direction.1 * last_y < 0 {
+            cache.cancel_prefetch();
+        }
+        if direction == (0, 0) || (direction == self.prefetch_direction && !window_changed) {
+            self.prefetch_direction = direction;
+            return;
+        }
+        self.prefetch_direction = direction;
+
+        // 预取深度: 加载耗时内能走过的距离, 至少一步跑步
+        let latency = cache.load_latency().as_secs_f32();
+        let depth = |speed: f32, size: f32| ((speed.abs() * latency / size).ceil() as i32).clamp(PREFETCH_MIN_TILES, PREFETCH_MAX_TILES);
+        let depth_x = depth(self.velocity.x, camera::TILE_WIDTH) * direction.0;
+        let depth_y = depth(self.velocity.y, camera::TILE_HEIGHT) * direction.1;
+
+        let (start_x, start_y, width, height) = self.window;
+        let in_window = |x: i32, y: i32| x >= start_x && x < start_x + width && y >= start_y && y < start_y + height;
+        let mut keys: Vec<CacheKey> = Vec::new();
+        for x in start_x + depth_x.min(0)..start_x + width + depth_x.max(0) {
+            for y in start_y + depth_y.min(0)..start_y + height + depth_y.max(0) {
+                if in_window(x, y) {
+                    continue;
+                }
+                if let Some(t) = self.build_tile_set(x, y, true) {
+                    if t.has_back() {
+                        keys.push(t.back_key);
+                    }
+                    if t.has_middle() {
+                        keys.push(t.middle_key);
+                    }
+                    if t.has_object() {
+                        keys.push(t.object_key);
+                    }
+                }
+            }
+        }
+        cache.prefetch_keys(keys.as_slice());
     }
 
-    fn build_map_window(&mut self, cache: &mut ImageCache, layer: i32) {
-        let max_width = self.max_tile_width + 4;
-        let max_height = self.max_tile_height + 12;
-        let start_x = self.current_tile_x - self.max_tile_width / 2 - 2;
-        let start_y = self.current_tile_y - self.max_tile_height / 2 - 2;
+    fn build_map_window(&mut self, cache: &mut ImageCache) {
+        (self.max_tile_width, self.max_tile_height) = self.camera.view_tiles();
+        let origin = self.camera.view_origin();
+        let (origin_x, origin_y) = camera::world_to_tile(origin.x, origin.y);
+        let (margin_x, margin_y) = self.object_margin();
+        // 地表层多取一格以覆盖从偶数格开始的 96x64 大地砖
+        let ground_x = origin_x - 1..=origin_x + self.max_tile_width;
+        let ground_y = origin_y - 1..=origin_y + self.max_tile_height;
+        // 物件以格子底部为锚点向上绘制, 需要多取下方的行和左侧的列
+        let max_width = self.max_tile_width + 2 + margin_x;
+        let max_height = self.max_tile_height + 2 + margin_y;
+        let start_x = origin_x - 1 - margin_x;
+        let start_y = origin_y - 1;
+        self.window = (start_x, start_y, max_width, max_height);
+        self.ground_window = (*ground_x.start(), *ground_y.start(), *ground_x.end(), *ground_y.end());
         let mut sets: Vec<MapTileSet> = Vec::new();
         // println!("max w: {}, h: {}, start x: {}, y: {}", max_width, max_height, start_x, start_y);
         for w in 0..max_width {
             for h in 0..max_height {
-                if w + start_x < 0 || w + start_x >= self.tile_height || h + start_y < 0 || h + start_y >= self.tile_width {
-                    continue;
+                let ground = ground_x.contains(&(w + start_x)) && ground_y.contains(&(h + start_y));
+                if let Some(set) = self.build_tile_set(w + start_x, h + start_y, ground) {
+                    sets.push(set);
                 }
-                let p = (w + start_x) * self.tile_height + h + start_y;
-                let tile = &self.tiles[p as usize];
-                let even = (w + start_x) & 0x1 != 1 && (h + start_y) & 0x1 != 1;
-                // cache::build_cache_key()
-
-                // println!("even: {even}, p: {p}, w: {w}, h: {h}, start_x: {start_x}, start_y: {start_y}, tile: {:?}", tile);
-
-                let back_idx = (tile.back as u32 & 0x7FFF);
-                let middle_idx = (tile.middle as u32 & 0x7FFF);
-                let object_idx = (tile.objects as u32 & 0x7FFF);
-                let back_idx = if back_idx > 0 { back_idx - 1 } else { 0 };
-                let middle_idx = if middle_idx > 0 { middle_idx - 1 } else { 0 };
-                let object_idx = if object_idx > 0 { object_idx - 1 } else { 0 };
-
-
-                sets.push(MapTileSet {
-                    layer: layer + (w + start_x) * 1024,
-                    even,
-                    tile: tile.clone(),
-                    x: w as f32 * 48.,
-                    y: h as f32 * 32.,
-                    back_key: CacheKey::from(self.data_id, self.data_number + 0, 2, 1, 1, tile.back_idx as u32 + 1, back_idx),
-                    middle_key: CacheKey::from(self.data_id, self.data_number + 1, 2, 1, 2, tile.middle_idx as u32 + 1, middle_idx),
-                    object_key: CacheKey::from(self.data_id, self.data_number + 2, 2, 1, 3, tile.objects_idx as u32 + 1, object_idx),
-                })
             }
         }
-        let back_keys = sets.iter().filter(|x| x.even && (x.tile.back & 0x7FFF) > 0).map(|t| {
-            t.back_key
-        }).collect::<Vec<CacheKey>>();
-        let middle_keys = sets.iter().filter(|x| (x.tile.middle & 0x7FFF) > 0).map(|t| {
-            t.middle_key
-        }).collect::<Vec<CacheKey>>();
-        let object_keys = sets.iter().filter(|x| (x.tile.objects & 0x7FFF) > 0).map(|t| {
-            t.object_key
-        }).collect::<Vec<CacheKey>>();
-        // println!("back keys len: {}", back_keys.len());
-        cache.load_keys(back_keys.as_slice());
-        cache.load_keys(middle_keys.as_slice());
+        // 地表层由 GroundChunks 按区块加载
+        let object_keys = sets.iter().filter(|x| x.has_object()).flat_map(|t| {
+            [Some(t.object_key), self.shadow_frame(&t.object_key)]
+        }).flatten().collect::<Vec<CacheKey>>();
         cache.load_keys(object_keys.as_slice());
         self.current_tile_set = sets;
     }
 
-    pub fn draw_tile(&mut self, canvas: &mut Canvas, ctx: &mut Context, cache: &mut ImageCache, layer: i32) {
+    /// 地表层可见的格子范围 (start_x, start_y, end_x, end_y), 包含两端
+    pub fn ground_window(&self) -> (i32, i32, i32, i32) {
+        self.ground_window
+    }
+
+    pub fn debug(&mut self) -> &mut MapDebugOverlay {
+        &mut self.debug
+    }
 
+    pub fn draw_debug(&self, ctx: &mut Context, canvas: &mut Canvas) {
+        if let Err(e) = self.debug.draw(ctx, canvas, self) {
+            error!("调试层绘制失败: {:?}", e);
+        }
+    }
+
+    /// 当前地图三层的图集 key, 切换地图时用于卸载
+    pub fn data_keys(&self) -> [u32; 3] {
+        let (back, middle) = self.ground_data_keys();
+        [back, middle, CacheKey::build_data_key(self.data_id, self.data_number + 2, 2)]
+    }
+
+    /// 可见格子中已加载图片的比例, 窗口尚未建立时为 0
+    pub fn loaded_ratio(&self, ctx: &mut Context, cache: &mut ImageCache) -> f32 {
         if self.reload {
-            self.reload = false;
-            self.build_map_window(cache, layer);
+            return 0.;
         }
+        let [back_key, middle_key, object_key] = self.data_keys();
+        let (back, middle, object) = (cache.get(ctx, &back_key), cache.get(ctx, &middle_key), cache.get(ctx, &object_key));
+        let loaded = |value: &Option<Arc<ImageValue>>, key: &CacheKey| {
+            value.as_ref().is_some_and(|v| v.meta(key.get_meta_key()).is_some())
+        };
+        let (mut total, mut count) = (0, 0);
+        for t in self.current_tile_set.iter() {
+            let layers = [
+                (t.ground && t.has_back(), &back, &t.back_key),
+                (t.ground && t.has_middle(), &middle, &t.middle_key),
+                (t.has_object(), &object, &t.object_key),
+            ];
+            for (wanted, value, key) in layers {
+                if wanted {
+                    total += 1;
+                    if loaded(value, key) {
+                        count += 1;
+                    }
+                }
+            }
+        }
+        if total == 0 { 1. } else { count as f32 / total as f32 }
+    }
 
-        let time = Instant::now();
-        // let image = ScreenImage::new(ctx, None, 1., 1., 1).image(ctx);
-        // let mut text_canvas = Canvas::from_image(ctx, image.clone(), None);
+    pub fn ground_data_keys(&self) -> (u32, u32) {
+        (CacheKey::build_data_key(self.data_id, self.data_number, 2), CacheKey::build_data_key(self.data_id, self.data_number + 1, 2))
+    }
 
+    /// 当前地图物件的最大尺寸, 地图加载时从图片头读取并按地图缓存, 读不到时用保守估计
+    pub fn object_extent(&self) -> (u32, u32) {
+        self.object_extents.get(&self.map_name).copied().unwrap_or(DEFAULT_OBJECT_EXTENT)
+    }
 
-        let rel_offset_x = (self.absolute_offset_x as i32 % 48) as f32;
-        let rel_offset_y = (self.absolute_offset_y as i32 % 32) as f32;
-        let back_data_key = CacheKey::build_data_key(self.data_id, self.data_number, 2);
+    fn object_margin(&self) -> (i32, i32) {
+        object_margin(self.object_extent())
+    }
 
-        let dest = DrawParam::default().dest(vec2(-3. * 48., -3. * 32.));
+    /// 已加载的图集中出现更大的物件(如开门的帧)时扩大范围, 只扩大不缩小,
+    /// 图集按 data_id 共享, 可能只包含一部分物件
+    fn update_object_extent(&mut self, extent: (u32, u32)) {
+        let current = self.object_extent();
+        let extent = (extent.0.max(current.0), extent.1.max(current.1));
+        if extent == current {
+            return;
+        }
+        let margin = self.object_margin();
+        self.object_extents.insert(self.map_name.clone(), extent);
+        if margin != self.object_margin() {
+            self.reload = true;
+        }
+    }
 
-        if let Some(value) = cache.get(ctx, &back_data_key) {
-            // println!("value: {}", value.)
-            let image_width = value.image().width() as f32;
-            let image_height = value.image().height() as f32;
-            let mut array = InstanceArray::new(ctx, value.image());
-            array.set(self.current_tile_set
-                .iter()
-                .filter(|t|t.even)
-                .filter(|t|{
-                    value.meta(t.back_key.get_meta_key()).is_some()
-                })
-                .map(|t|{
-                    let meta = value.meta(t.back_key.get_meta_key()).unwrap();
-                    DrawParam::default().src(Rect::new(meta.src_x / image_width, meta.src_y / image_height, meta.width as f32 / image_width, meta.height as f32 / image_height))
-                        .dest(vec2(meta.offset_x + t.x + rel_offset_x, meta.offset_y + t.y + rel_offset_y))
-            }));
-            // text_canvas.finish(ctx).unwrap();
-            canvas.draw(&array, dest);
-            // canvas.draw(&image, DrawParam::default());
-            // canvas.draw(&value.image(), DrawParam::default());
-        }
-        // println!("draw back: {:?}", time.elapsed());
-        let middle_data_key = CacheKey::build_data_key(self.data_id, self.data_number + 1, 2);
-        if let Some(value) = cache.get(ctx, &middle_data_key) {
-            let mut array = InstanceArray::new(ctx, value.image());
-            let image_width = value.image().width() as f32;
-            let image_height = value.image().height() as f32;
-            array.set(self.current_tile_set
-                .iter()
-                .filter(|t|value.meta(t.middle_key.get_meta_key()).is_some())
-                .map(|t|{
-                    let meta = value.meta(t.middle_key.get_meta_key()).unwrap();
-                    DrawParam::default().src(Rect::new(meta.src_x / image_width, meta.src_y / image_height, meta.width as f32 / image_width, meta.height as f32 / image_height))
-                        .dest(vec2(meta.offset_x + t.x + rel_offset_x, meta.offset_y + t.y + rel_offset_y))
-                }));
-            canvas.draw(&array, dest);
+    /// 后台读到的图片头代替保守估计, 与已加载图集中出现的尺寸取较大值
+    fn receive_object_extents(&mut self) {
+        while let Ok((name, extent)) = self.extent_receiver.try_recv() {
+            let margin = self.object_margin();
+            let value = self.object_extents.entry(name).or_insert(extent);
+            *value = (value.0.max(extent.0), value.1.max(extent.1));
+            if margin != self.object_margin() {
+                self.reload = true;
+            }
+        }
+    }
+
+    pub fn draw_tile(&mut self, canvas: &mut Canvas, ctx: &mut Context, cache: &mut ImageCache) {
+        self.receive_object_extents();
+        let object_data_key = CacheKey::build_data_key(self.data_id, self.data_number + 2, 2);
+        if let Some(value) = cache.get(ctx, &object_data_key) {
+            self.update_object_extent(value.max_size());
         }
-        // println!("draw middle: {:?}", time.elapsed());
 
+        if self.reload {
+            self.reload = false;
+            self.build_map_window(cache);
+            self.prefetch(cache, true);
+        } else {
+            self.prefetch(cache, false);
+        }
 
+        let mut chunks = std::mem::take(&mut self.ground_chunks);
+        chunks.draw(ctx, canvas, cache, self, self.ground_window);
+        self.ground_chunks = chunks;
+        self.draw_flat_objects(ctx, canvas, cache);
+    }
 
+    pub fn draw_param(&self) -> DrawParam {
+        self.camera.draw_param()
+    }
 
+    /// 48x32 的物件是贴地的, 在地表层绘制, 不参与深度排序
+    fn is_flat(meta: &ImageMeta) -> bool {
+        meta.width == 48 && meta.height == 32
     }
 
-    pub fn draw_objects(&mut self, ctx: &mut Context, canvas: &mut Canvas, cache: &mut ImageCache) {
-        let rel_offset_x = (self.absolute_offset_x as i32 % 48) as f32;
-        let rel_offset_y = (self.absolute_offset_y as i32 % 32) as f32;
-        let dest = DrawParam::default().dest(vec2(-3. * 48., -3. * 32.));
+    fn draw_flat_objects(&mut self, ctx: &mut Context, canvas: &mut Canvas, cache: &mut ImageCache) {
         let object_data_key = CacheKey::build_data_key(self.data_id, self.data_number + 2, 2);
         if let Some(value) = cache.get(ctx, &object_data_key) {
             let mut array = InstanceArray::new(ctx, value.image());
@@ -241,15 +524,67 @@ impl MapDraw {
             let image_height = value.image().height() as f32;
             array.set(self.current_tile_set
                 .iter()
-                .filter(|t|value.meta(t.object_key.get_meta_key()).is_some())
-                .map(|t|{
-                    let meta = value.meta(t.object_key.get_meta_key()).unwrap();
+                .filter_map(|t| value.meta(t.object_key.get_meta_key()).map(|meta| (t, meta)))
+                .filter(|(_, meta)| Self::is_flat(meta))
+                .map(|(t, meta)|{
                     DrawParam::default().src(Rect::new(meta.src_x / image_width, meta.src_y / image_height, meta.width as f32 / image_width, meta.height as f32 / image_height))
-                        .dest(vec2(meta.offset_x + t.x + rel_offset_x, meta.offset_y + t.y + rel_offset_y - meta.height as f32))
+                        .dest(vec2(meta.offset_x + t.x, meta.offset_y + t.y - meta.height as f32))
                 }));
-            canvas.draw(&array, dest);
+            canvas.draw(&array, self.draw_param());
+        }
+    }
+
+    /// 把可见的立体物件按格子行加入队列, 角色由调用方在同一队列中加入
+    pub fn queue_objects(&self, ctx: &mut Context, cache: &mut ImageCache, queue: &mut RenderQueue) {
+        let object_data_key = CacheKey::build_data_key(self.data_id, self.data_number + 2, 2);
+        let value = cache.get(ctx, &object_data_key);
+        for t in self.current_tile_set.iter().filter(|t| t.has_object()) {
+            let meta = value.as_ref().and_then(|value| value.meta(t.object_key.get_meta_key()));
+            if meta.is_some_and(Self::is_flat) {
+                continue;
+            }
+            // 影子帧与物件同样以格子底部为锚点, 生成的影子以物件图片底边为地面
+            if let (Some(meta), Some(shadow)) = (meta, self.object_shadow(&t.object_key)) {
+                let sprite = match shadow.offset {
+                    Some(_) => self.shadow_frame(&t.object_key).map(|key| Sprite::new(key, t.x, t.y, SpriteStyle::shadow())),
+                    None => Some(Sprite { skew: Some(t.y + meta.offset_y), ..Sprite::new(t.object_key, t.x, t.y, SpriteStyle::shadow()) }),
+                };
+                if let Some(sprite) = sprite {
+                    queue.push_shadow_sprite(t.layer, Sprite { bottom: true, ..sprite });
+                }
+            }
+            queue.push(t.layer, Sprite { bottom: true, ..Sprite::new(t.object_key, t.x, t.y, SpriteStyle::default()) });
         }
     }
 
+    /// 影子表中包含该物件图片的一项
+    fn object_shadow(&self, key: &CacheKey) -> Option<&ObjectShadow> {
+        let (file, index) = (key.get_file_number(), key.get_file_index() as u32);
+        self.object_shadows.iter().find(|shadow| shadow.contains(file, index))
+    }
+
+    /// 影子表为物件指定的影子帧, 与物件在同一图集
+    fn shadow_frame(&self, key: &CacheKey) -> Option<CacheKey> {
+        let offset = self.object_shadow(key)?.offset?;
+        Some(CacheKey::from(self.data_id, self.data_number + 2, 2, 1, OBJECTS_FILE_ID, key.get_file_number(), key.get_file_index() as u32 + offset))
+    }
+
+    /// actors 把角色加入同一队列, 与物件一起按深度绘制
+    pub fn draw_objects(&mut self, ctx: &mut Context, canvas: &mut Canvas, cache: &mut ImageCache, actors: impl FnOnce(&mut ImageCache, &mut RenderQueue)) {
+        let mut queue = std::mem::take(&mut self.render_queue);
+        queue.clear();
+        self.queue_objects(ctx, cache, &mut queue);
+        actors(cache, &mut queue);
+        queue.draw(ctx, canvas, cache, self.draw_param());
+        self.render_queue = queue;
+    }
 
-}
\ No newline at end of file
+    /// 魔法效果画在物件和角色之上, 混合方式由各精灵决定
+    pub fn draw_effects(&mut self, ctx: &mut Context, canvas: &mut Canvas, cache: &mut ImageCache, effects: impl FnOnce(&mut ImageCache, &mut RenderQueue)) {
+        let mut queue = std::mem::take(&mut self.render_queue);
+        queue.clear();
+        effects(cache, &mut queue);
+        queue.draw(ctx, canvas, cache, self.draw_param());
+        self.render_queue = queue;
+    }
+}
@@ -0,0 +1,151 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use ggez::Context;
+use ggez::glam::vec2;
+use ggez::graphics::{Canvas, Color, DrawParam, Image, ImageFormat, Quad, Rect};
+use image::RgbaImage;
+use tracing::{error, info};
+use crate::asset;
+use crate::cache::CacheKey;
+use crate::draw::headless::{self, ArchiveSet, MapRenderer};
+
+pub const MMAP_FILE_ID: u32 = 4;
+/// 生成缩略图时每个格子的像素大小, 保持 48:32 的比例
+pub const OVERVIEW_CELL: (u32, u32) = (3, 2);
+
+/// 地图缩略图, 在后台线程读取(或生成并缓存到磁盘), 供小地图和世界地图窗口绘制
+pub struct Minimap {
+    map_name: String,
+    map_size: (u32, u32),
+    image: Option<Image>,
+    receiver: Option<Receiver<Option<RgbaImage>>>,
+}
+
+impl Minimap {
+    /// mmap_index 为游戏自带小地图的编号, 没有时按地图生成; map_size 取自已读取的地图, 不再重复读取
+    pub fn request(base_dir: &Path, map_name: &str, map_size: (u32, u32), data_id: u32, data_number: u32, mmap_index: Option<u32>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let base_dir = base_dir.to_path_buf();
+        let name = String::from(map_name);
+        thread::spawn(move || {
+            let image = load_or_generate(&base_dir, &name, data_id, data_number, mmap_index);
+            sender.send(image).ok();
+        });
+        Self {
+            map_name: String::from(map_name),
+            map_size,
+            image: None,
+            receiver: Some(receiver),
+        }
+    }
+
+    /// 把后台线程生成的图片上传到 GPU
+    pub fn update(&mut self, ctx: &mut Context) {
+        if let Some(receiver) = &self.receiver {
+            if let Ok(result) = receiver.try_recv() {
+                self.receiver = None;
+                match result {
+                    Some(rgba) => {
+                        self.image = Some(Image::from_pixels(ctx, rgba.as_raw(), ImageFormat::Rgba8UnormSrgb, rgba.width(), rgba.height()));
+                    }
+                    None => error!("小地图生成失败: {}", self.map_name),
+                }
+            }
+        }
+    }
+
+    /// 格子在缩略图中的像素位置
+    fn tile_to_image(&self, image: &Image, tile_x: f32, tile_y: f32) -> (f32, f32) {
+        if self.map_size.0 == 0 || self.map_size.1 == 0 {
+            return (0., 0.);
+        }
+        (tile_x / self.map_size.0 as f32 * image.width() as f32, tile_y / self.map_size.1 as f32 * image.height() as f32)
+    }
+
+    /// 小地图: 以玩家为中心裁剪, 缩放 zoom 倍画到 dest 区域
+    pub fn draw_minimap(&self, canvas: &mut Canvas, dest: Rect, player_tile: (i32, i32), zoom: f32) {
+        let image = match &self.image {
+            Some(image) => image,
+            None => return,
+        };
+        let (image_width, image_height) = (image.width() as f32, image.height() as f32);
+        let (px, py) = self.tile_to_image(image, player_tile.0 as f32 + 0.5, player_tile.1 as f32 + 0.5);
+        let (src_width, src_height) = ((dest.w / zoom).min(image_width), (dest.h / zoom).min(image_height));
+        let src_x = (px - src_width / 2.).clamp(0., image_width - src_width);
+        let src_y = (py - src_height / 2.).clamp(0., image_height - src_height);
+        canvas.draw(image, DrawParam::default()
+            .src(Rect::new(src_x / image_width, src_y / image_height, src_width / image_width, src_height / image_height))
+            .dest(vec2(dest.x, dest.y))
+            .scale(vec2(dest.w / src_width, dest.h / src_height)));
+        let marker = vec2(dest.x + (px - src_x) * dest.w / src_width, dest.y + (py - src_y) * dest.h / src_height);
+        draw_marker(canvas, marker.x, marker.y);
+    }
+
+    /// 世界地图: 整张缩略图按比例缩放到 dest 区域
+    pub fn draw_world_map(&self, canvas: &mut Canvas, dest: Rect, player_tile: (i32, i32)) {
+        let image = match &self.image {
+            Some(image) => image,
+            None => return,
+        };
+        let scale = (dest.w / image.width() as f32).min(dest.h / image.height() as f32);
+        let (offset_x, offset_y) = (dest.x + (dest.w - image.width() as f32 * scale) / 2., dest.y + (dest.h - image.height() as f32 * scale) / 2.);
+        canvas.draw(image, DrawParam::default().dest(vec2(offset_x, offset_y)).scale(vec2(scale, scale)));
+        let (px, py) = self.tile_to_image(image, player_tile.0 as f32 + 0.5, player_tile.1 as f32 + 0.5);
+        draw_marker(canvas, offset_x + px * scale, offset_y + py * scale);
+    }
+}
+
+fn draw_marker(canvas: &mut Canvas, x: f32, y: f32) {
+    canvas.draw(&Quad, DrawParam::default().dest(vec2(x - 2., y - 2.)).scale(vec2(4., 4.)).color(Color::RED));
+}
+
+fn cache_path(base_dir: &Path, map_name: &str) -> PathBuf {
+    base_dir.join("cache").join("minimap").join(map_name.to_lowercase()).with_extension("png")
+}
+
+fn map_path(base_dir: &Path, map_name: &str) -> PathBuf {
+    base_dir.join("map").join(map_name).with_extension("map")
+}
+
+/// 缓存比地图文件旧(地图被替换过)时作废
+fn is_cache_fresh(cache: &Path, map: &Path) -> bool {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    match (modified(cache), modified(map)) {
+        (Some(cache), Some(map)) => cache >= map,
+        (Some(_), None) => true,
+        _ => false,
+    }
+}
+
+fn load_or_generate(base_dir: &Path, map_name: &str, data_id: u32, data_number: u32, mmap_index: Option<u32>) -> Option<RgbaImage> {
+    let path = cache_path(base_dir, map_name);
+    if is_cache_fresh(&path, &map_path(base_dir, map_name)) {
+        if let Ok(image) = image::open(&path) {
+            return Some(image.to_rgba8());
+        }
+    }
+    let mut archives = ArchiveSet::new(base_dir.join("data"));
+    let image = match mmap_index {
+        Some(index) => {
+            archives.add_name(MMAP_FILE_ID, String::from("mmap"));
+            headless::to_rgba(archives.get(CacheKey::from(0, 0, 2, 1, MMAP_FILE_ID, 1, index)))
+        }
+        None => None,
+    };
+    let image = match image {
+        Some(image) => image,
+        None => {
+            let map = asset::read_map_file(map_path(base_dir, map_name))?;
+            MapRenderer::new(&map, &mut archives, data_id, data_number).overview(OVERVIEW_CELL.0, OVERVIEW_CELL.1)
+        }
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).ok();
+    }
+    match headless::write_png(&image, &path) {
+        Ok(_) => info!("小地图已缓存: {:?}", path),
+        Err(e) => error!("小地图缓存失败: {:?}", e),
+    }
+    Some(image)
+}
@@ -1 +1,13 @@
-pub mod map;
\ No newline at end of file
+pub mod actor;
+pub mod blend;
+pub mod camera;
+pub mod chunk;
+pub mod debug;
+pub mod effect;
+pub mod frame;
+pub mod headless;
+pub mod map;
+pub mod minimap;
+pub mod order;
+pub mod player;
+pub mod text;
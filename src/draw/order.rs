@@ -0,0 +1,200 @@
+use ggez::Context;
+use ggez::glam::{Mat4, vec2, vec4};
+use ggez::graphics::{Canvas, DrawParam, InstanceArray, Rect, Shader};
+use tracing::error;
+use crate::cache::{CacheKey, ImageCache, ImageMeta};
+use crate::draw::blend::{self, SpriteBlend, SpriteStyle};
+
+/// 同一行内先画物件再画角色, 角色会被下一行的物件遮挡
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DepthLayer {
+    Object = 1,
+    Actor = 2,
+}
+
+/// 生成的影子: 高度压扁的比例, 以及每升高一个像素向右斜切的像素
+const SHADOW_FLATTEN: f32 = 0.5;
+const SHADOW_SHEAR: f32 = 0.6;
+
+/// 深度值: 行优先, 同行按层, 同层按列
+pub fn depth(tile_y: i32, layer: DepthLayer, tile_x: i32) -> i64 {
+    ((tile_y as i64) << 32) | ((layer as i64) << 24) | (tile_x as i64 & 0xFFFFFF)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Sprite {
+    pub key: CacheKey,
+    pub x: f32,
+    pub y: f32,
+    /// 物件以格子底部为锚点, 需要向上减去图片高度
+    pub bottom: bool,
+    pub style: SpriteStyle,
+    /// 生成的影子: 以该 y(世界坐标) 为地面把图片压扁并斜切
+    pub skew: Option<f32>,
+}
+
+impl Sprite {
+    pub fn new(key: CacheKey, x: f32, y: f32, style: SpriteStyle) -> Self {
+        Self { key, x, y, bottom: false, style, skew: None }
+    }
+
+    /// 图片在图集中的位置和绘制参数
+    fn param(&self, meta: &ImageMeta, image_width: f32, image_height: f32) -> DrawParam {
+        let y = if self.bottom { self.y - meta.height as f32 } else { self.y };
+        let (x, y) = (meta.offset_x + self.x, meta.offset_y + y);
+        let param = DrawParam::default()
+            .src(Rect::new(meta.src_x / image_width, meta.src_y / image_height, meta.width as f32 / image_width, meta.height as f32 / image_height))
+            .color(self.style.tint);
+        match self.skew {
+            // 图片内 (u, v) 变换到 (x + u + (ground - y - v) * shear, ground - (ground - y - v) * flatten)
+            Some(ground) => param.transform(Mat4::from_cols(
+                vec4(1., 0., 0., 0.),
+                vec4(-SHADOW_SHEAR, SHADOW_FLATTEN, 0., 0.),
+                vec4(0., 0., 1., 0.),
+                vec4(x + (ground - y) * SHADOW_SHEAR, ground - (ground - y) * SHADOW_FLATTEN, 0., 1.),
+            )),
+            None => param.dest(vec2(x, y)),
+        }
+    }
+}
+
+/// 深度队列, 影子单独一组, 在所有物件和角色之前绘制
+#[derive(Default)]
+pub struct RenderQueue {
+    items: Vec<(i64, Sprite)>,
+    shadows: Vec<(i64, Sprite)>,
+    /// 染色用的着色器, 第一次用到时创建
+    dye_shader: Option<Shader>,
+}
+
+impl RenderQueue {
+    pub fn new() -> Self {
+        Self { items: Vec::new(), shadows: Vec::new(), dye_shader: None }
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+        self.shadows.clear();
+    }
+
+    pub fn push(&mut self, depth: i64, sprite: Sprite) {
+        self.items.push((depth, sprite));
+    }
+
+    /// 带混合方式和染色的角色层精灵
+    pub fn push_styled(&mut self, tile_x: i32, tile_y: i32, key: CacheKey, x: f32, y: f32, style: SpriteStyle) {
+        self.push(depth(tile_y, DepthLayer::Actor, tile_x), Sprite::new(key, x, y, style));
+    }
+
+    /// 图片库提供的影子帧, 半透明画在地面上
+    pub fn push_shadow(&mut self, tile_x: i32, tile_y: i32, key: CacheKey, x: f32, y: f32) {
+        self.shadows.push((depth(tile_y, DepthLayer::Actor, tile_x), Sprite::new(key, x, y, SpriteStyle::shadow())));
+    }
+
+    /// 已设置好锚点和斜切的影子, 与其他影子一起在最前面绘制
+    pub fn push_shadow_sprite(&mut self, depth: i64, sprite: Sprite) {
+        self.shadows.push((depth, sprite));
+    }
+
+    /// 没有影子帧时用图片本身生成影子, ground 为脚下地面的 y(世界坐标)
+    pub fn push_silhouette(&mut self, tile_x: i32, tile_y: i32, key: CacheKey, x: f32, y: f32, ground: f32) {
+        let sprite = Sprite { skew: Some(ground), ..Sprite::new(key, x, y, SpriteStyle::shadow()) };
+        self.shadows.push((depth(tile_y, DepthLayer::Actor, tile_x), sprite));
+    }
+
+    /// 稳定排序, 同一深度保持加入顺序(角色的身体/头发/武器)
+    pub fn sort(&mut self) {
+        self.items.sort_by_key(|(depth, _)| *depth);
+        self.shadows.sort_by_key(|(depth, _)| *depth);
+    }
+
+    /// 先画影子, 再按深度顺序绘制物件和角色
+    pub fn draw(&mut self, ctx: &mut Context, canvas: &mut Canvas, cache: &mut ImageCache, dest: DrawParam) {
+        self.sort();
+        Self::draw_items(&self.shadows, &mut self.dye_shader, ctx, canvas, cache, dest);
+        Self::draw_items(&self.items, &mut self.dye_shader, ctx, canvas, cache, dest);
+    }
+
+    /// 切换混合方式, 染色还要换成去色的着色器, 创建失败时按原色绘制
+    fn set_blend(canvas: &mut Canvas, ctx: &Context, dye_shader: &mut Option<Shader>, from: SpriteBlend, to: SpriteBlend) {
+        canvas.set_blend_mode(to.mode());
+        if to == SpriteBlend::Dye {
+            if dye_shader.is_none() {
+                match blend::dye_shader(ctx) {
+                    Ok(shader) => *dye_shader = Some(shader),
+                    Err(e) => error!("染色着色器创建失败: {:?}", e),
+                }
+            }
+            if let Some(shader) = dye_shader {
+                canvas.set_shader(shader);
+            }
+        } else if from == SpriteBlend::Dye {
+            canvas.set_default_shader();
+        }
+    }
+
+    /// 相邻且同一图集, 同一混合方式的精灵合并为一个 InstanceArray,
+    /// 混合方式只在变化时切换, 画完恢复为 Alpha
+    fn draw_items(items: &[(i64, Sprite)], dye_shader: &mut Option<Shader>, ctx: &mut Context, canvas: &mut Canvas, cache: &mut ImageCache, dest: DrawParam) {
+        let mut blend = SpriteBlend::Alpha;
+        let mut start = 0;
+        while start < items.len() {
+            let data_key = items[start].1.key.get_data_key();
+            let batch_blend = items[start].1.style.blend;
+            let end = items[start..].iter()
+                .position(|(_, s)| s.key.get_data_key() != data_key || s.style.blend != batch_blend)
+                .map_or(items.len(), |p| start + p);
+            if batch_blend != blend {
+                Self::set_blend(canvas, ctx, dye_shader, blend, batch_blend);
+                blend = batch_blend;
+            }
+            if let Some(value) = cache.get(ctx, &data_key) {
+                let image_width = value.image().width() as f32;
+                let image_height = value.image().height() as f32;
+                let mut array = InstanceArray::new(ctx, value.image());
+                array.set(items[start..end]
+                    .iter()
+                    .filter_map(|(_, s)| value.meta(s.key.get_meta_key()).map(|meta| s.param(meta, image_width, image_height))));
+                canvas.draw(&array, dest);
+            }
+            start = end;
+        }
+        if blend != SpriteBlend::Alpha {
+            Self::set_blend(canvas, ctx, dye_shader, blend, SpriteBlend::Alpha);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(index: u32) -> CacheKey {
+        CacheKey::from(1, 1, 2, 1, 1, 1, index)
+    }
+
+    fn object(queue: &mut RenderQueue, tile_x: i32, tile_y: i32, index: u32) {
+        queue.push(depth(tile_y, DepthLayer::Object, tile_x), Sprite::new(key(index), 0., 0., SpriteStyle::default()));
+    }
+
+    #[test]
+    fn actor_between_object_rows() {
+        let mut queue = RenderQueue::new();
+        object(&mut queue, 3, 11, 1);
+        queue.push_styled(5, 10, key(2), 0., 0., SpriteStyle::default());
+        object(&mut queue, 7, 9, 3);
+        // 同一格的身体和武器保持加入顺序
+        queue.push_styled(5, 10, key(4), 0., 0., SpriteStyle::default());
+        object(&mut queue, 900, 10, 5);
+        queue.sort();
+        let order = queue.items.iter().map(|(_, s)| s.key).collect::<Vec<_>>();
+        assert_eq!(order, [3, 5, 2, 4, 1].map(key));
+    }
+
+    #[test]
+    fn depth_packs_row_layer_column() {
+        assert!(depth(0, DepthLayer::Actor, 0xFFFFFF) < depth(1, DepthLayer::Object, 0));
+        assert!(depth(4, DepthLayer::Object, 0xFFFFFF) < depth(4, DepthLayer::Actor, 0));
+        assert!(depth(4, DepthLayer::Actor, 1) < depth(4, DepthLayer::Actor, 2));
+    }
+}
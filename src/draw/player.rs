@@ -0,0 +1,207 @@
+use std::collections::HashSet;
+use crate::account::{CharacterInfo, Gender};
+use crate::asset::ActionFrames;
+use crate::cache::{CacheKey, ImageCache};
+use crate::draw::blend::{self, Poison, SpriteStyle};
+use crate::draw::camera;
+use crate::draw::order::RenderQueue;
+
+pub const HUM_FILE_ID: u32 = 8;
+pub const HAIR_FILE_ID: u32 = 9;
+pub const WEAPON_FILE_ID: u32 = 10;
+/// 人物图集的 data_id, data_number 为外观编号, 每套外观一个图集
+pub const HUM_DATA_ID: u32 = 201;
+pub const HAIR_DATA_ID: u32 = 202;
+pub const WEAPON_DATA_ID: u32 = 203;
+pub const PLAYER_FRAMES: u32 = 600;
+
+/// 人物动作, 每个动作 8 个方向
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PlayerAction {
+    Stand,
+    Walk,
+    Run,
+    Hit,
+    Spell,
+    Struck,
+    Die,
+}
+
+impl PlayerAction {
+    /// 传奇标准的人物帧布局
+    pub fn frames(&self) -> ActionFrames {
+        match self {
+            PlayerAction::Stand => ActionFrames::new(0, 4, 4, 0.2),
+            PlayerAction::Walk => ActionFrames::new(64, 6, 2, 0.1),
+            PlayerAction::Run => ActionFrames::new(128, 6, 2, 0.1),
+            PlayerAction::Hit => ActionFrames::new(200, 6, 2, 0.085),
+            PlayerAction::Spell => ActionFrames::new(392, 6, 2, 0.06),
+            PlayerAction::Struck => ActionFrames::new(472, 3, 5, 0.07),
+            PlayerAction::Die => ActionFrames::new(536, 4, 4, 0.12),
+        }
+    }
+}
+
+/// 人物的图层, 分别来自 Hum, Hair, Weapon
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PlayerLayer {
+    Body,
+    Hair,
+    Weapon,
+}
+
+impl PlayerLayer {
+    fn data_id(&self) -> u32 {
+        match self {
+            PlayerLayer::Body => HUM_DATA_ID,
+            PlayerLayer::Hair => HAIR_DATA_ID,
+            PlayerLayer::Weapon => WEAPON_DATA_ID,
+        }
+    }
+
+    fn file_id(&self) -> u32 {
+        match self {
+            PlayerLayer::Body => HUM_FILE_ID,
+            PlayerLayer::Hair => HAIR_FILE_ID,
+            PlayerLayer::Weapon => WEAPON_FILE_ID,
+        }
+    }
+}
+
+/// 武器在身体后面的方向(背对屏幕时右手被身体挡住)
+const WEAPON_BEHIND: [bool; 8] = [true, true, false, false, false, false, false, true];
+
+/// 各方向图层的绘制顺序, 先画的在下面
+pub fn layer_order(direction: u8) -> [PlayerLayer; 3] {
+    if WEAPON_BEHIND[direction as usize % 8] {
+        [PlayerLayer::Weapon, PlayerLayer::Body, PlayerLayer::Hair]
+    } else {
+        [PlayerLayer::Body, PlayerLayer::Hair, PlayerLayer::Weapon]
+    }
+}
+
+/// 人物外观: 衣服, 发型, 武器编号, 头发和武器为 0 时不画
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayerLook {
+    pub dress: u16,
+    pub hair: u16,
+    pub weapon: u16,
+    pub gender: Gender,
+    /// 染发编号, 0 为原色
+    pub dye: u8,
+}
+
+impl PlayerLook {
+    pub fn new(dress: u16, hair: u16, weapon: u16, gender: Gender) -> Self {
+        Self { dress, hair, weapon, gender, dye: 0 }
+    }
+
+    pub fn from_character(info: &CharacterInfo) -> Self {
+        Self::new(info.dress, 1, 0, info.gender)
+    }
+
+    /// 图层的外观编号, 每种外观男女各一套
+    pub fn appearance(&self, layer: PlayerLayer) -> Option<u32> {
+        let value = match layer {
+            PlayerLayer::Body => self.dress,
+            PlayerLayer::Hair if self.hair > 0 => self.hair,
+            PlayerLayer::Weapon if self.weapon > 0 => self.weapon,
+            _ => return None,
+        };
+        Some(value as u32 * 2 + if self.gender == Gender::Female { 1 } else { 0 })
+    }
+}
+
+/// 某一帧的图片
+pub fn frame_key(layer: PlayerLayer, appearance: u32, action: PlayerAction, direction: u8, frame: u32) -> CacheKey {
+    let index = appearance * PLAYER_FRAMES + action.frames().index(direction, frame);
+    CacheKey::from(layer.data_id(), appearance, 2, 1, layer.file_id(), 1, index)
+}
+
+/// 整个动作 8 个方向的图片
+pub fn action_key(layer: PlayerLayer, appearance: u32, action: PlayerAction) -> CacheKey {
+    let frames = action.frames();
+    CacheKey::from(layer.data_id(), appearance, 2, frames.count(), layer.file_id(), 1, appearance * PLAYER_FRAMES + frames.start)
+}
+
+/// 要画的一帧, direction 为 0~7
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlayerFrame {
+    pub action: PlayerAction,
+    pub direction: u8,
+    pub frame: u32,
+}
+
+impl PlayerFrame {
+    pub fn new(action: PlayerAction, direction: u8, frame: u32) -> Self {
+        Self { action, direction, frame }
+    }
+}
+
+/// 玩家精灵: 按外观把动作帧解析为各图层的图片, 第一次用到某个动作时整段加载
+pub struct PlayerSprite {
+    look: PlayerLook,
+    poison: Poison,
+    loaded: HashSet<(PlayerLayer, u32, PlayerAction)>,
+}
+
+impl PlayerSprite {
+    pub fn new(look: PlayerLook) -> Self {
+        Self { look, poison: Poison::None, loaded: HashSet::new() }
+    }
+
+    pub fn poison(&self) -> Poison {
+        self.poison
+    }
+
+    pub fn set_poison(&mut self, poison: Poison) {
+        self.poison = poison;
+    }
+
+    /// 图层的染色: 头发按染发编号, 中毒时整体再染色
+    pub fn style(&self, layer: PlayerLayer) -> SpriteStyle {
+        let style = match layer {
+            PlayerLayer::Hair => SpriteStyle::dyed(blend::hair_dye(self.look.dye)),
+            _ => SpriteStyle::default(),
+        };
+        style.tint(self.poison.tint())
+    }
+
+    pub fn set_look(&mut self, look: PlayerLook) {
+        self.look = look;
+    }
+
+    pub fn load(&mut self, cache: &mut ImageCache, action: PlayerAction) {
+        let keys = [PlayerLayer::Body, PlayerLayer::Hair, PlayerLayer::Weapon].into_iter()
+            .filter_map(|layer| self.look.appearance(layer).map(|appearance| (layer, appearance)))
+            .filter(|(layer, appearance)| self.loaded.insert((*layer, *appearance, action)))
+            .map(|(layer, appearance)| action_key(layer, appearance, action))
+            .collect::<Vec<CacheKey>>();
+        if !keys.is_empty() {
+            cache.load_keys(keys.as_slice());
+        }
+    }
+
+    /// 按绘制顺序排列的各图层图片
+    pub fn keys(&self, frame: PlayerFrame) -> Vec<CacheKey> {
+        self.layers(frame).into_iter().map(|(_, key)| key).collect()
+    }
+
+    fn layers(&self, frame: PlayerFrame) -> Vec<(PlayerLayer, CacheKey)> {
+        layer_order(frame.direction).into_iter()
+            .filter_map(|layer| self.look.appearance(layer).map(|appearance| (layer, frame_key(layer, appearance, frame.action, frame.direction, frame.frame))))
+            .collect()
+    }
+
+    /// 加入深度队列, position 为所在格子左上角的世界坐标, 图片偏移以此为原点.
+    /// 人物图片库没有影子帧, 用身体生成影子
+    pub fn queue(&mut self, cache: &mut ImageCache, queue: &mut RenderQueue, tile: (i32, i32), position: (f32, f32), frame: PlayerFrame) {
+        self.load(cache, frame.action);
+        for (layer, key) in self.layers(frame) {
+            if layer == PlayerLayer::Body {
+                queue.push_silhouette(tile.0, tile.1, key, position.0, position.1, position.1 + camera::TILE_HEIGHT / 2.);
+            }
+            queue.push_styled(tile.0, tile.1, key, position.0, position.1, self.style(layer));
+        }
+    }
+}
@@ -0,0 +1,372 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use ggez::Context;
+use ggez::glam::vec2;
+use ggez::graphics::{Canvas, Color, DrawParam, FontData, Rect, Text, TextFragment};
+use tracing::{error, info, warn};
+
+pub const FONT_NAME: &str = "cjk";
+/// 字体配置, 第一行为字体文件路径(相对资源目录)
+const FONT_CONFIG: &str = "config/font.txt";
+const DEFAULT_FONT_FILE: &str = "font/simsun.ttf";
+/// 配置的字体不可用时依次尝试的系统中文字体
+const SYSTEM_FONTS: [&str; 6] = [
+    "C:/Windows/Fonts/simsun.ttc",
+    "C:/Windows/Fonts/msyh.ttc",
+    "/usr/share/fonts/opentype/noto/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/noto-cjk/NotoSansCJK-Regular.ttc",
+    "/usr/share/fonts/truetype/wqy/wqy-microhei.ttc",
+    "/System/Library/Fonts/PingFang.ttc",
+];
+pub const LINK_COLOR: Color = Color::new(1., 0.85, 0.3, 1.);
+
+/// 文字效果, 人物名字用描边
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextEffect {
+    None,
+    /// 右下偏移 1 像素的阴影
+    Shadow(Color),
+    /// 8 个方向各偏移 1 像素的描边
+    Outline(Color),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextStyle {
+    pub size: f32,
+    pub color: Color,
+    pub effect: TextEffect,
+}
+
+impl TextStyle {
+    pub fn new(size: f32, color: Color) -> Self {
+        Self { size, color, effect: TextEffect::None }
+    }
+
+    pub fn shadow(mut self) -> Self {
+        self.effect = TextEffect::Shadow(Color::BLACK);
+        self
+    }
+
+    pub fn outline(mut self) -> Self {
+        self.effect = TextEffect::Outline(Color::BLACK);
+        self
+    }
+}
+
+/// 同一颜色的一段文字, link 为 NPC 对话中点击后执行的命令
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub color: Color,
+    pub link: Option<String>,
+}
+
+/// 按颜色名或 #RRGGBB 取颜色
+pub fn parse_color(value: &str) -> Option<Color> {
+    let value = value.trim();
+    if let Some(hex) = value.strip_prefix('#') {
+        let rgb = u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 6)?;
+        return Some(Color::from_rgb((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8));
+    }
+    let color = match value.to_ascii_lowercase().as_str() {
+        "red" | "红" => Color::from_rgb(255, 0, 0),
+        "green" | "绿" => Color::from_rgb(0, 255, 0),
+        "blue" | "蓝" => Color::from_rgb(80, 120, 255),
+        "yellow" | "黄" => Color::from_rgb(255, 255, 0),
+        "white" | "白" => Color::WHITE,
+        "black" | "黑" => Color::BLACK,
+        "cyan" | "青" => Color::from_rgb(0, 255, 255),
+        "purple" | "紫" => Color::from_rgb(255, 0, 255),
+        "orange" | "橙" => Color::from_rgb(255, 160, 0),
+        "gray" | "grey" | "灰" => Color::from_rgb(160, 160, 160),
+        "pink" | "粉" => Color::from_rgb(255, 150, 200),
+        _ => return None,
+    };
+    Some(color)
+}
+
+/// 解析聊天和 NPC 对话中的标记:
+/// `{文字/颜色}` 为彩色文字, `<文字/@命令>` 为可点击的链接, 无法识别的标记原样显示
+pub fn parse_rich(value: &str, color: Color) -> Vec<Span> {
+    let mut result: Vec<Span> = Vec::new();
+    let mut push = |text: &str, color: Color, link: Option<String>| {
+        if text.is_empty() {
+            return;
+        }
+        match result.last_mut() {
+            Some(last) if last.color == color && last.link.is_none() && link.is_none() => last.text.push_str(text),
+            _ => result.push(Span { text: String::from(text), color, link }),
+        }
+    };
+    let mut rest = value;
+    while let Some(start) = rest.find(['{', '<']) {
+        let close = if rest[start..].starts_with('{') { '}' } else { '>' };
+        let end = match rest[start..].find(close) {
+            Some(end) => start + end,
+            None => break,
+        };
+        let inner = &rest[start + 1..end];
+        let tag = inner.rsplit_once('/').and_then(|(text, arg)| match close {
+            '}' => parse_color(arg).map(|c| Span { text: String::from(text), color: c, link: None }),
+            _ => arg.strip_prefix('@').map(|command| Span { text: String::from(text), color: LINK_COLOR, link: Some(String::from(command)) }),
+        });
+        match tag {
+            Some(span) => {
+                push(&rest[..start], color, None);
+                push(&span.text, span.color, span.link);
+            }
+            None => push(&rest[..end + 1], color, None),
+        }
+        rest = &rest[end + 1..];
+    }
+    push(rest, color, None);
+    result
+}
+
+/// 排版后的多行文字, 坐标相对左上角
+#[derive(Debug, Clone, Default)]
+pub struct TextBlock {
+    pub lines: Vec<Vec<Span>>,
+    pub width: f32,
+    pub height: f32,
+    size: f32,
+    line_height: f32,
+}
+
+/// 文字渲染: 加载中文字体, 缓存字符宽度, 按像素宽度换行
+#[derive(Debug, Clone, Default)]
+pub struct TextRenderer {
+    /// 为空时使用 ggez 自带字体(不含中文)
+    font: Option<String>,
+    widths: HashMap<(char, u32), f32>,
+}
+
+impl TextRenderer {
+    /// 加载配置的字体, 失败时尝试系统中文字体, 都没有时使用 ggez 自带字体, 中文显示为方块
+    pub fn load(ctx: &mut Context, base_dir: &Path) -> Self {
+        let file = fs::read_to_string(base_dir.join(FONT_CONFIG)).ok()
+            .and_then(|c| c.lines().map(str::trim).find(|l| !l.is_empty() && !l.starts_with(';')).map(String::from))
+            .unwrap_or_else(|| String::from(DEFAULT_FONT_FILE));
+        let candidates = std::iter::once(base_dir.join(&file)).chain(SYSTEM_FONTS.iter().map(|f| Path::new(f).to_path_buf()));
+        for path in candidates {
+            let font = fs::read(&path).map_err(|e| e.to_string())
+                .and_then(|data| FontData::from_vec(data).map_err(|e| e.to_string()));
+            match font {
+                Ok(data) => {
+                    ctx.gfx.add_font(FONT_NAME, data);
+                    info!("加载字体: {:?}", path);
+                    return Self { font: Some(String::from(FONT_NAME)), widths: HashMap::new() };
+                }
+                Err(e) => warn!("字体加载失败: {:?} {}", path, e),
+            }
+        }
+        error!("没有可用的中文字体, 使用默认字体, 请把字体放到 {:?} 或在 {} 中指定字体文件", base_dir.join(DEFAULT_FONT_FILE), FONT_CONFIG);
+        Self::default()
+    }
+
+    pub fn line_height(&self, size: f32) -> f32 {
+        size.ceil() + 2.
+    }
+
+    fn fragment(&self, value: &str, size: f32, color: Color) -> TextFragment {
+        let fragment = TextFragment::new(value).scale(size).color(color);
+        match &self.font {
+            Some(font) => fragment.font(font.as_str()),
+            None => fragment,
+        }
+    }
+
+    pub fn text(&self, value: &str, size: f32, color: Color) -> Text {
+        Text::new(self.fragment(value, size, color))
+    }
+
+    pub fn char_width(&mut self, ctx: &Context, c: char, size: f32) -> f32 {
+        if let Some(width) = self.widths.get(&(c, size.to_bits())) {
+            return *width;
+        }
+        // 空白字符没有字形, 用前后加字符的差值
+        let width = if c.is_whitespace() {
+            let measure = |v: &str| self.text(v, size, Color::WHITE).measure(ctx).map_or(0., |m| m.x);
+            measure(&format!("i{}i", c)) - measure("ii")
+        } else {
+            self.text(&c.to_string(), size, Color::WHITE).measure(ctx).map_or(size, |m| m.x)
+        };
+        self.widths.insert((c, size.to_bits()), width);
+        width
+    }
+
+    pub fn measure(&mut self, ctx: &Context, value: &str, size: f32) -> f32 {
+        value.chars().map(|c| self.char_width(ctx, c, size)).sum()
+    }
+
+    /// 解析标记并排版, max_width 为空时只在换行符处换行
+    pub fn layout(&mut self, ctx: &Context, value: &str, size: f32, color: Color, max_width: Option<f32>) -> TextBlock {
+        self.layout_spans(ctx, parse_rich(value, color), size, max_width)
+    }
+
+    /// 不解析标记, 用于输入框等用户输入的文字
+    pub fn layout_plain(&mut self, ctx: &Context, value: &str, size: f32, color: Color) -> TextBlock {
+        self.layout_spans(ctx, vec![Span { text: String::from(value), color, link: None }], size, None)
+    }
+
+    fn layout_spans(&mut self, ctx: &Context, spans: Vec<Span>, size: f32, max_width: Option<f32>) -> TextBlock {
+        let line_height = self.line_height(size);
+        let max_width = max_width.unwrap_or(f32::MAX);
+        let mut block = TextBlock { lines: vec![Vec::new()], size, line_height, ..Default::default() };
+        let mut x = 0.;
+        for span in spans {
+            for word in split_words(&span.text) {
+                if word == "\n" {
+                    block.lines.push(Vec::new());
+                    x = 0.;
+                    continue;
+                }
+                let width = self.measure(ctx, word, size);
+                if x + width > max_width && x > 0. {
+                    block.lines.push(Vec::new());
+                    x = 0.;
+                    if word.trim().is_empty() {
+                        continue;
+                    }
+                }
+                // 超长的单词按字符断开
+                let mut pieces = Vec::new();
+                if width > max_width {
+                    let mut piece = String::new();
+                    let mut piece_width = 0.;
+                    for c in word.chars() {
+                        let w = self.char_width(ctx, c, size);
+                        if piece_width + w > max_width && !piece.is_empty() {
+                            pieces.push((std::mem::take(&mut piece), piece_width));
+                            piece_width = 0.;
+                        }
+                        piece.push(c);
+                        piece_width += w;
+                    }
+                    pieces.push((piece, piece_width));
+                } else {
+                    pieces.push((String::from(word), width));
+                }
+                for (i, (piece, width)) in pieces.into_iter().enumerate() {
+                    if i > 0 {
+                        block.lines.push(Vec::new());
+                        x = 0.;
+                    }
+                    let line = block.lines.last_mut().unwrap();
+                    match line.last_mut() {
+                        Some(last) if last.color == span.color && last.link == span.link => last.text.push_str(&piece),
+                        _ => line.push(Span { text: piece, color: span.color, link: span.link.clone() }),
+                    }
+                    x += width;
+                    block.width = block.width.max(x);
+                }
+            }
+        }
+        block.height = block.lines.len() as f32 * line_height;
+        block
+    }
+
+    fn line_text(&self, line: &[Span], size: f32, color: Option<Color>) -> Text {
+        let mut text = Text::default();
+        for span in line {
+            text.add(self.fragment(&span.text, size, color.unwrap_or(span.color)));
+        }
+        text
+    }
+
+    pub fn draw_block(&self, canvas: &mut Canvas, block: &TextBlock, x: f32, y: f32, effect: TextEffect) {
+        for (i, line) in block.lines.iter().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+            let (x, y) = (x.round(), (y + i as f32 * block.line_height).round());
+            let offsets: &[(f32, f32)] = match effect {
+                TextEffect::None => &[],
+                TextEffect::Shadow(_) => &[(1., 1.)],
+                TextEffect::Outline(_) => &[(-1., -1.), (0., -1.), (1., -1.), (-1., 0.), (1., 0.), (-1., 1.), (0., 1.), (1., 1.)],
+            };
+            if let TextEffect::Shadow(color) | TextEffect::Outline(color) = effect {
+                let back = self.line_text(line, block.size, Some(color));
+                for (dx, dy) in offsets {
+                    canvas.draw(&back, DrawParam::default().dest(vec2(x + dx, y + dy)));
+                }
+            }
+            canvas.draw(&self.line_text(line, block.size, None), DrawParam::default().dest(vec2(x, y)));
+        }
+    }
+
+    /// 在 rect 中居中
+    pub fn draw_centered(&mut self, ctx: &Context, canvas: &mut Canvas, value: &str, rect: Rect, style: TextStyle) {
+        let block = self.layout(ctx, value, style.size, style.color, None);
+        let (x, y) = (rect.x + (rect.w - block.width) / 2., rect.y + (rect.h - block.height) / 2.);
+        self.draw_block(canvas, &block, x, y, style.effect);
+    }
+
+    /// 人物头顶的名字, (x, y) 为名字底边中点
+    pub fn draw_name(&mut self, ctx: &Context, canvas: &mut Canvas, name: &str, x: f32, y: f32, color: Color) {
+        let style = TextStyle::new(12., color).outline();
+        let block = self.layout(ctx, name, style.size, color, None);
+        self.draw_block(canvas, &block, x - block.width / 2., y - block.height, style.effect);
+    }
+
+    /// 按 rect 的宽度换行, 返回排版结果用于判断链接点击
+    pub fn draw_wrapped(&mut self, ctx: &Context, canvas: &mut Canvas, value: &str, rect: Rect, style: TextStyle) -> TextBlock {
+        let block = self.layout(ctx, value, style.size, style.color, Some(rect.w));
+        self.draw_block(canvas, &block, rect.x, rect.y, style.effect);
+        block
+    }
+}
+
+/// 连续的字母数字为一个单词, 其余(中文, 标点, 空白)每个字符单独断开
+fn split_words(value: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut start = None;
+    for (i, c) in value.char_indices() {
+        if c.is_ascii_alphanumeric() {
+            start.get_or_insert(i);
+            continue;
+        }
+        if let Some(s) = start.take() {
+            result.push(&value[s..i]);
+        }
+        result.push(&value[i..i + c.len_utf8()]);
+    }
+    if let Some(s) = start {
+        result.push(&value[s..]);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(spans: &[Span]) -> Vec<&str> {
+        spans.iter().map(|s| s.text.as_str()).collect()
+    }
+
+    #[test]
+    fn rich_color_and_link() {
+        let spans = parse_rich("你好{勇士/red}, 请<接受任务/@accept>", Color::WHITE);
+        assert_eq!(texts(&spans), vec!["你好", "勇士", ", 请", "接受任务"]);
+        assert_eq!(spans[1].color, Color::from_rgb(255, 0, 0));
+        assert_eq!(spans[2].color, Color::WHITE);
+        assert_eq!(spans[3].color, LINK_COLOR);
+        assert_eq!(spans[3].link.as_deref(), Some("accept"));
+        assert_eq!(parse_rich("{绿色/#00FF00}", Color::WHITE)[0].color, Color::from_rgb(0, 255, 0));
+    }
+
+    #[test]
+    fn rich_unknown_tags_kept() {
+        let spans = parse_rich("a{b/nocolor}c<d/e>f{g", Color::WHITE);
+        assert_eq!(texts(&spans), vec!["a{b/nocolor}c<d/e>f{g"]);
+        assert!(parse_rich("", Color::WHITE).is_empty());
+    }
+
+    #[test]
+    fn words() {
+        assert_eq!(split_words("hello 世界abc1!"), vec!["hello", " ", "世", "界", "abc1", "!"]);
+        assert!(split_words("").is_empty());
+    }
+}
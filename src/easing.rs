@@ -13,12 +13,18 @@ pub enum EasingStatus {
     Stop,
 }
 
-#[derive(CanTween, Clone, Default)]
+#[derive(CanTween, Clone, Copy, Default, Debug, PartialEq)]
 pub struct Point2 {
     pub x: f32,
     pub y: f32,
 }
 
+impl Point2 {
+    pub fn new(x: f32, y: f32) -> Self {
+        Self { x, y }
+    }
+}
+
 #[derive(CanTween, Clone, Default)]
 pub struct Rect2 {
     pub x: f32,
@@ -35,7 +41,7 @@ pub struct Easing<T: CanTween + Clone + Default> {
 impl<T: CanTween + Clone + Default> Easing<T> {
     pub fn new(start: T, finish: T, time: f64) -> Self {
         let sequence = keyframes![
-            (start, time, keyframe::functions::Linear),
+            (start, 0.0, keyframe::functions::Linear),
             (finish, time, keyframe::functions::Linear)
         ];
         Self {
@@ -82,6 +88,21 @@ impl<T: CanTween + Clone + Default> Easing<T> {
         }
     }
 
+    /// 单次播放, 到达终点后停止, 返回是否已结束
+    pub fn advance(&mut self, duration: f64) -> bool {
+        if self.status == EasingStatus::Run {
+            self.sequence.advance_by(duration);
+            if self.sequence.finished() {
+                self.stop();
+            }
+        }
+        self.status == EasingStatus::Stop
+    }
+
+    pub fn progress(&self) -> f64 {
+        self.sequence.progress()
+    }
+
     pub fn now(&self) -> T {
         self.sequence.now()
     }
@@ -106,6 +127,26 @@ pub fn angle8(src_x: f32, src_y: f32, dst_x: f32, dst_y: f32) -> f32 {
     return sharing(angle(src_x, src_y, dst_x, dst_y), 8.);
 }
 
+/// angle8 方向(1 为正上, 顺时针)对应的格子偏移
+pub fn direction_offset(direction: u8) -> (i32, i32) {
+    match direction {
+        1 => (0, -1),
+        2 => (1, -1),
+        3 => (1, 0),
+        4 => (1, 1),
+        5 => (0, 1),
+        6 => (-1, 1),
+        7 => (-1, 0),
+        8 => (-1, -1),
+        _ => (0, 0),
+    }
+}
+
+/// 格子偏移对应的 angle8 方向, 无偏移时为 0
+pub fn offset_direction(dx: i32, dy: i32) -> u8 {
+    (1..=8).find(|d| direction_offset(*d) == (dx.signum(), dy.signum())).unwrap_or(0)
+}
+
 pub fn angle12(src_x: f32, src_y: f32, dst_x: f32, dst_y: f32) -> f32 {
     return sharing(angle(src_x, src_y, dst_x, dst_y), 12.);
 }
@@ -5,8 +5,13 @@ use tracing::info;
 use tracing_subscriber::EnvFilter;
 use tracing_subscriber::fmt::format::Writer;
 use tracing_subscriber::fmt::time::FormatTime;
+use crate::account::{LocalAuth, MockAccountService};
+use crate::scene::SceneManager;
+use crate::scene::login::LoginScene;
+use crate::scene::role::DEFAULT_MAX_CHARACTERS;
 use crate::test_cache::TestCacheApp;
 
+mod account;
 mod scene;
 mod asset;
 // mod cache_bak;
@@ -16,6 +21,7 @@ mod draw;
 mod cache;
 mod easing;
 mod control;
+mod ui;
 
 struct LocalTimer;
 
@@ -41,16 +47,33 @@ fn main() -> GameResult {
         path::PathBuf::from("./")
     };
     info!("RUN DIR: {:?}", resource_dir);
+    // 无窗口渲染地图区域到 PNG
+    let args: Vec<String> = env::args().collect();
+    if args.get(1).map(String::as_str) == Some("render") {
+        return draw::headless::run(&resource_dir, &args[2..]);
+    }
     let cb = ggez::ContextBuilder::new("D32", "iX")
         .add_resource_path(resource_dir.clone())
         .window_setup(WindowSetup::default().title("D32"))
-        .window_mode(WindowMode::default().dimensions(1920.0, 1280.0));
+        .window_mode(WindowMode::default().dimensions(1920.0, 1280.0).resizable(true).resize_on_scale_factor_change(true));
 
     let (mut ctx, event_loop) = cb.build()?;
 
-    let app = TestCacheApp::new(&resource_dir, &mut ctx);
-
-    event::run(ctx, event_loop, app)
+    // test: 直接进入地图测试
+    if args.get(1).map(String::as_str) == Some("test") {
+        let app = TestCacheApp::new(&resource_dir, &mut ctx)?;
+        event::run(ctx, event_loop, app)
+    } else {
+        // 本地认证和角色服务, 不连接服务器
+        let roles = MockAccountService::new(DEFAULT_MAX_CHARACTERS);
+        let app = SceneManager::new(&mut ctx, &resource_dir, |state| Box::new(LoginScene::new(
+            state,
+            Box::new(LocalAuth::new()),
+            Box::new(move || Box::new(roles.clone())),
+            DEFAULT_MAX_CHARACTERS,
+        )));
+        event::run(ctx, event_loop, app)
+    }
 }
 
 
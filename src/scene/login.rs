@@ -0,0 +1,391 @@
+use ggez::{Context, GameResult};
+use ggez::event::MouseButton;
+use ggez::graphics::{Canvas, Color, Rect};
+use ggez::input::keyboard::{KeyCode, KeyInput};
+use tracing::info;
+use crate::account::{self, AccountService, AuthBackend, AuthRequest, AuthResponse, NewAccount, Session};
+use crate::asset::{self, ServerInfo};
+use crate::cache::ImageCache;
+use crate::control::GameState;
+use crate::draw::frame::{self, CHRSEL_FILE_ID, PRGUSE_FILE_ID};
+use crate::scene::role::RoleScene;
+use crate::scene::{Scene, SceneAction, SceneEnum};
+use crate::ui::{Anchor, Ui, UiEvent, Widget, WidgetId};
+
+/// ChrSel 中的登录背景
+const BACKGROUND_INDEX: u32 = 22;
+/// Prguse 中的登录, 注册, 修改密码对话框
+const LOGIN_DIALOG_INDEX: u32 = 60;
+const NEW_ACCOUNT_DIALOG_INDEX: u32 = 63;
+const CHANGE_PASSWORD_DIALOG_INDEX: u32 = 50;
+const SERVER_DIALOG_INDEX: u32 = 256;
+/// Prguse 中按钮按下时的图片
+const OK_BUTTON_INDEX: u32 = 62;
+const NEW_ACCOUNT_BUTTON_INDEX: u32 = 61;
+const CHANGE_PASSWORD_BUTTON_INDEX: u32 = 53;
+const CANCEL_BUTTON_INDEX: u32 = 52;
+const CLOSE_BUTTON_INDEX: u32 = 64;
+
+const ERROR_COLOR: Color = Color::new(1., 0.4, 0.3, 1.);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Page {
+    Login,
+    NewAccount,
+    ChangePassword,
+    Servers,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Button {
+    Login,
+    NewAccount,
+    ChangePassword,
+    Submit,
+    Cancel,
+    Server(usize),
+    Quit,
+}
+
+/// 当前打开的对话框, 控件在 Ui 中
+struct Dialog {
+    page: Page,
+    window: WidgetId,
+    inputs: Vec<WidgetId>,
+    buttons: Vec<(WidgetId, Button)>,
+    list: Option<WidgetId>,
+    message: WidgetId,
+}
+
+impl Dialog {
+    fn open(ui: &mut Ui, page: Page, image: u32, size: (f32, f32)) -> Self {
+        ui.clear();
+        let window = ui.add(Widget::window(size.0, size.1).anchor(Anchor::Center).frame(frame::frame_key(PRGUSE_FILE_ID, image)));
+        let message = ui.add_child(window, Widget::label(Rect::new(20., size.1 + 10., size.0 - 40., 16.), "").color(ERROR_COLOR));
+        Self { page, window, inputs: Vec::new(), buttons: Vec::new(), list: None, message }
+    }
+
+    fn input(&mut self, ui: &mut Ui, label: &str, y: f32, password: bool, max_len: usize) -> WidgetId {
+        ui.add_child(self.window, Widget::label(Rect::new(40., y + 3., 90., 16.), label));
+        let input = Widget::input(Rect::new(130., y, 170., 20.), max_len);
+        let id = ui.add_child(self.window, if password { input.password() } else { input });
+        if self.inputs.is_empty() {
+            ui.focus(id);
+        }
+        self.inputs.push(id);
+        id
+    }
+
+    fn button(&mut self, ui: &mut Ui, rect: Rect, button: Button, image: u32, label: &str) {
+        let id = ui.add_child(self.window, Widget::button(rect, label).pressed_frame(frame::frame_key(PRGUSE_FILE_ID, image)));
+        self.buttons.push((id, button));
+    }
+
+    /// 账号已填写时焦点放到密码框
+    fn fill_account(&self, ui: &mut Ui, account: &str) {
+        if let Some(id) = self.inputs.first() {
+            ui.set_text(*id, account);
+            if !account.is_empty() {
+                ui.focus(self.inputs[1]);
+            }
+        }
+    }
+
+    fn login(ui: &mut Ui, account: &str) -> Self {
+        let mut d = Self::open(ui, Page::Login, LOGIN_DIALOG_INDEX, (352., 220.));
+        d.input(ui, "账号", 60., false, 10);
+        d.input(ui, "密码", 90., true, 10);
+        d.fill_account(ui, account);
+        d.button(ui, Rect::new(130., 130., 80., 24.), Button::Login, OK_BUTTON_INDEX, "登录");
+        d.button(ui, Rect::new(220., 130., 80., 24.), Button::Quit, CLOSE_BUTTON_INDEX, "退出");
+        d.button(ui, Rect::new(40., 175., 120., 24.), Button::NewAccount, NEW_ACCOUNT_BUTTON_INDEX, "注册账号");
+        d.button(ui, Rect::new(190., 175., 120., 24.), Button::ChangePassword, CHANGE_PASSWORD_BUTTON_INDEX, "修改密码");
+        d
+    }
+
+    fn new_account(ui: &mut Ui) -> Self {
+        let mut d = Self::open(ui, Page::NewAccount, NEW_ACCOUNT_DIALOG_INDEX, (352., 330.));
+        d.input(ui, "账号", 50., false, 10);
+        d.input(ui, "密码", 80., true, 10);
+        d.input(ui, "确认密码", 110., true, 10);
+        d.input(ui, "姓名", 140., false, 20);
+        d.input(ui, "密码问题", 170., false, 20);
+        d.input(ui, "问题答案", 200., false, 20);
+        d.button(ui, Rect::new(90., 260., 80., 24.), Button::Submit, OK_BUTTON_INDEX, "确定");
+        d.button(ui, Rect::new(190., 260., 80., 24.), Button::Cancel, CANCEL_BUTTON_INDEX, "取消");
+        d
+    }
+
+    fn change_password(ui: &mut Ui, account: &str) -> Self {
+        let mut d = Self::open(ui, Page::ChangePassword, CHANGE_PASSWORD_DIALOG_INDEX, (352., 240.));
+        d.input(ui, "账号", 50., false, 10);
+        d.input(ui, "原密码", 80., true, 10);
+        d.input(ui, "新密码", 110., true, 10);
+        d.input(ui, "确认密码", 140., true, 10);
+        d.fill_account(ui, account);
+        d.button(ui, Rect::new(90., 190., 80., 24.), Button::Submit, OK_BUTTON_INDEX, "确定");
+        d.button(ui, Rect::new(190., 190., 80., 24.), Button::Cancel, CANCEL_BUTTON_INDEX, "取消");
+        d
+    }
+
+    /// 服务器较多时列表可以滚动, 选中后进入
+    fn servers(ui: &mut Ui, servers: &[ServerInfo]) -> Self {
+        let mut d = Self::open(ui, Page::Servers, SERVER_DIALOG_INDEX, (352., 300.));
+        let list = ui.add_child(d.window, Widget::list(Rect::new(56., 50., 222., 182.), 26.));
+        let bar = ui.add_child(d.window, Widget::scrollbar(Rect::new(280., 50., 14., 182.)));
+        ui.set_items(list, servers.iter().map(|s| s.name.clone()).collect());
+        ui.bind_scrollbar(list, bar);
+        ui.set_selected(list, Some(0));
+        d.list = Some(list);
+        d.button(ui, Rect::new(90., 250., 80., 24.), Button::Submit, OK_BUTTON_INDEX, "进入");
+        d.button(ui, Rect::new(190., 250., 80., 24.), Button::Cancel, CANCEL_BUTTON_INDEX, "返回");
+        d
+    }
+
+    fn selected_server(&self, ui: &Ui) -> Option<usize> {
+        self.list.and_then(|list| ui.selected(list))
+    }
+
+    fn text<'a>(&self, ui: &'a Ui, i: usize) -> &'a str {
+        self.inputs.get(i).map_or("", |id| ui.text(*id))
+    }
+
+    fn button_of(&self, id: WidgetId) -> Option<Button> {
+        self.buttons.iter().find(|(b, _)| *b == id).map(|(_, b)| *b)
+    }
+}
+
+/// 登录场景: 账号密码, 注册, 修改密码, 选择服务器
+pub struct LoginScene {
+    ui: Ui,
+    dialog: Dialog,
+    servers: Vec<ServerInfo>,
+    auth: Box<dyn AuthBackend>,
+    /// 选择服务器后创建角色服务
+    roles: Box<dyn Fn() -> Box<dyn AccountService>>,
+    max_characters: usize,
+    waiting: bool,
+    account: String,
+    session: Option<u32>,
+    next: Option<SceneAction>,
+}
+
+impl LoginScene {
+    pub fn new(state: &GameState, auth: Box<dyn AuthBackend>, roles: Box<dyn Fn() -> Box<dyn AccountService>>, max_characters: usize) -> Self {
+        let mut servers = asset::read_server_list(state.base_dir.join("config").join("servers.txt"));
+        if servers.is_empty() {
+            servers.push(ServerInfo { name: String::from("本地服务器"), host: String::from("127.0.0.1"), port: 7000 });
+        }
+        let mut ui = Ui::new(state.window_size.0, state.window_size.1);
+        let dialog = Dialog::login(&mut ui, "");
+        Self {
+            ui,
+            dialog,
+            servers,
+            auth,
+            roles,
+            max_characters,
+            waiting: false,
+            account: String::new(),
+            session: None,
+            next: None,
+        }
+    }
+
+    fn open(&mut self, page: Page) {
+        let account = self.account.clone();
+        self.dialog = match page {
+            Page::Login => Dialog::login(&mut self.ui, &account),
+            Page::NewAccount => Dialog::new_account(&mut self.ui),
+            Page::ChangePassword => {
+                let account = String::from(self.dialog.text(&self.ui, 0));
+                Dialog::change_password(&mut self.ui, &account)
+            }
+            Page::Servers => Dialog::servers(&mut self.ui, &self.servers),
+        };
+    }
+
+    fn set_message(&mut self, message: &str) {
+        self.ui.set_text(self.dialog.message, message);
+    }
+
+    fn press(&mut self, button: Button, state: &mut GameState) {
+        if self.waiting {
+            return;
+        }
+        match button {
+            Button::Login => self.submit(),
+            Button::NewAccount => self.open(Page::NewAccount),
+            Button::ChangePassword => self.open(Page::ChangePassword),
+            Button::Submit if self.dialog.page == Page::Servers => match self.dialog.selected_server(&self.ui) {
+                Some(i) => self.press(Button::Server(i), state),
+                None => self.set_message("请选择服务器"),
+            },
+            Button::Submit => self.submit(),
+            Button::Cancel => {
+                self.session = None;
+                self.open(Page::Login);
+            }
+            Button::Server(i) => {
+                let server = self.servers[i].clone();
+                info!("选择服务器: {} {}:{}", server.name, server.host, server.port);
+                state.session = Some(Session { account: self.account.clone(), session_id: self.session.unwrap_or(0), server: Some(server), character: None });
+                self.next = Some(SceneAction::Push(Box::new(RoleScene::new(state, (self.roles)(), self.max_characters))));
+            }
+            Button::Quit => self.next = Some(SceneAction::Quit),
+        }
+    }
+
+    /// 校验当前对话框的输入, 通过后发给认证后端
+    fn submit(&mut self) {
+        let (d, ui) = (&self.dialog, &self.ui);
+        let text = |i| d.text(ui, i);
+        let request = match d.page {
+            Page::Login => account::validate_account(text(0))
+                .and_then(|_| account::validate_password(text(1)))
+                .map(|_| AuthRequest::Login { account: String::from(text(0)), password: String::from(text(1)) }),
+            Page::NewAccount => account::validate_account(text(0))
+                .and_then(|_| account::validate_password(text(1)))
+                .and_then(|_| if text(1) == text(2) { Ok(()) } else { Err(String::from("两次输入的密码不一致")) })
+                .and_then(|_| if text(3).trim().is_empty() { Err(String::from("请输入姓名")) } else { Ok(()) })
+                .map(|_| AuthRequest::NewAccount(NewAccount {
+                    account: String::from(text(0)),
+                    password: String::from(text(1)),
+                    user_name: String::from(text(3).trim()),
+                    question: String::from(text(4).trim()),
+                    answer: String::from(text(5).trim()),
+                })),
+            Page::ChangePassword => account::validate_account(text(0))
+                .and_then(|_| account::validate_password(text(2)))
+                .and_then(|_| if text(2) == text(3) { Ok(()) } else { Err(String::from("两次输入的密码不一致")) })
+                .and_then(|_| if text(1) == text(2) { Err(String::from("新密码不能与原密码相同")) } else { Ok(()) })
+                .map(|_| AuthRequest::ChangePassword {
+                    account: String::from(text(0)),
+                    password: String::from(text(1)),
+                    new_password: String::from(text(2)),
+                }),
+            Page::Servers => return,
+        };
+        match request {
+            Ok(request) => {
+                self.account = String::from(text(0));
+                self.waiting = true;
+                self.set_message("请稍候...");
+                self.auth.send(request);
+            }
+            Err(e) => self.set_message(&e),
+        }
+    }
+
+    fn handle_response(&mut self, response: AuthResponse) {
+        self.waiting = false;
+        match response {
+            AuthResponse::Login(Ok(session)) => {
+                info!("登录成功: {}", self.account);
+                self.session = Some(session);
+                self.open(Page::Servers);
+            }
+            AuthResponse::NewAccount(Ok(_)) => {
+                self.open(Page::Login);
+                self.set_message("注册成功, 请登录");
+            }
+            AuthResponse::ChangePassword(Ok(_)) => {
+                self.open(Page::Login);
+                self.set_message("密码已修改, 请重新登录");
+            }
+            AuthResponse::Login(Err(e)) | AuthResponse::NewAccount(Err(e)) | AuthResponse::ChangePassword(Err(e)) => self.set_message(&e),
+        }
+    }
+}
+
+impl Scene for LoginScene {
+    fn kind(&self) -> SceneEnum {
+        SceneEnum::Login
+    }
+
+    fn enter(&mut self, _ctx: &mut Context, state: &mut GameState, cache: &mut ImageCache) {
+        // 从选择角色返回时重新登录
+        if self.session.take().is_some() {
+            state.session = None;
+            self.open(Page::Login);
+        }
+        self.ui.resize(state.window_size.0, state.window_size.1);
+        let mut keys = vec![frame::frame_key(CHRSEL_FILE_ID, BACKGROUND_INDEX)];
+        keys.extend([
+            LOGIN_DIALOG_INDEX, NEW_ACCOUNT_DIALOG_INDEX, CHANGE_PASSWORD_DIALOG_INDEX, SERVER_DIALOG_INDEX,
+            OK_BUTTON_INDEX, NEW_ACCOUNT_BUTTON_INDEX, CHANGE_PASSWORD_BUTTON_INDEX, CANCEL_BUTTON_INDEX, CLOSE_BUTTON_INDEX,
+        ].map(|index| frame::frame_key(PRGUSE_FILE_ID, index)));
+        cache.load_keys(&keys);
+    }
+
+    fn update(&mut self, _ctx: &mut Context, state: &mut GameState, _cache: &mut ImageCache) -> GameResult<SceneAction> {
+        while let Some(event) = self.ui.poll_event() {
+            match event {
+                UiEvent::Clicked(id) => if let Some(button) = self.dialog.button_of(id) {
+                    self.press(button, state);
+                },
+                UiEvent::Submitted(_) => self.press(Button::Submit, state),
+                _ => {}
+            }
+        }
+        while let Some(response) = self.auth.poll() {
+            self.handle_response(response);
+        }
+        Ok(self.next.take().unwrap_or(SceneAction::None))
+    }
+
+    fn draw(&mut self, ctx: &mut Context, canvas: &mut Canvas, state: &mut GameState, cache: &mut ImageCache) -> GameResult {
+        let (window_width, window_height) = state.window_size;
+        let background = frame::frame_key(CHRSEL_FILE_ID, BACKGROUND_INDEX);
+        if let Some((w, h)) = frame::frame_size(ctx, cache, background) {
+            frame::draw_frame(ctx, canvas, cache, background, (window_width - w) / 2., (window_height - h) / 2., Color::WHITE);
+        }
+        self.ui.draw(ctx, canvas, cache, &mut state.text)
+    }
+
+    fn resize(&mut self, _ctx: &mut Context, state: &GameState) {
+        self.ui.resize(state.window_size.0, state.window_size.1);
+    }
+
+    fn mouse_button_down(&mut self, _ctx: &mut Context, _state: &mut GameState, button: MouseButton, x: f32, y: f32) -> GameResult {
+        self.ui.mouse_button_down(button, x, y);
+        Ok(())
+    }
+
+    fn mouse_button_up(&mut self, _ctx: &mut Context, _state: &mut GameState, button: MouseButton, x: f32, y: f32) -> GameResult {
+        self.ui.mouse_button_up(button, x, y);
+        Ok(())
+    }
+
+    fn mouse_motion(&mut self, _ctx: &mut Context, _state: &mut GameState, x: f32, y: f32, _dx: f32, _dy: f32) -> GameResult {
+        self.ui.mouse_motion(x, y);
+        Ok(())
+    }
+
+    fn mouse_wheel(&mut self, _ctx: &mut Context, _state: &mut GameState, x: f32, y: f32) -> GameResult {
+        self.ui.mouse_wheel(x, y);
+        Ok(())
+    }
+
+    fn key_down(&mut self, _ctx: &mut Context, state: &mut GameState, input: KeyInput, _repeated: bool) -> GameResult {
+        if self.waiting {
+            return Ok(());
+        }
+        if self.ui.key_down(input) {
+            return Ok(());
+        }
+        match input.keycode {
+            Some(KeyCode::Return) | Some(KeyCode::NumpadEnter) if self.dialog.page == Page::Servers => self.press(Button::Submit, state),
+            Some(KeyCode::Escape) if self.dialog.page != Page::Login => self.press(Button::Cancel, state),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn text_input(&mut self, _ctx: &mut Context, _state: &mut GameState, character: char) -> GameResult {
+        if !self.waiting {
+            self.ui.text_input(character);
+        }
+        Ok(())
+    }
+}
@@ -1,10 +1,296 @@
+use std::path::Path;
+use ggez::{Context, GameError, GameResult};
+use ggez::event::{EventHandler, MouseButton};
+use ggez::glam::vec2;
+use ggez::graphics::{Canvas, Color, DrawParam, Quad};
+use ggez::input::keyboard::KeyInput;
+use tracing::{info, warn};
+use crate::cache::ImageCache;
+use crate::control::GameState;
+use crate::easing::Easing;
 
+pub mod login;
+pub mod player;
+pub mod role;
+
+pub const SCENE_FADE_TIME: f64 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SceneEnum {
     Login,
     Role,
     Player,
 }
 
-pub struct Scene {
-    se: SceneEnum
-}
\ No newline at end of file
+/// 场景在 update 中返回的切换请求
+pub enum SceneAction {
+    None,
+    Push(Box<dyn Scene>),
+    Pop,
+    Replace(Box<dyn Scene>),
+    Quit,
+}
+
+/// 场景, 由 SceneManager 转发事件, GameState 和 ImageCache 在场景间共享
+pub trait Scene {
+    fn kind(&self) -> SceneEnum;
+
+    /// 成为栈顶场景时调用
+    fn enter(&mut self, _ctx: &mut Context, _state: &mut GameState, _cache: &mut ImageCache) {}
+
+    /// 被覆盖或移出栈时调用
+    fn leave(&mut self, _ctx: &mut Context, _state: &mut GameState, _cache: &mut ImageCache) {}
+
+    fn update(&mut self, ctx: &mut Context, state: &mut GameState, cache: &mut ImageCache) -> GameResult<SceneAction>;
+
+    fn draw(&mut self, ctx: &mut Context, canvas: &mut Canvas, state: &mut GameState, cache: &mut ImageCache) -> GameResult;
+
+    /// 为 true 时先绘制下层场景, 用于对话框等半透明场景
+    fn is_overlay(&self) -> bool {
+        false
+    }
+
+    fn resize(&mut self, _ctx: &mut Context, _state: &GameState) {}
+
+    fn mouse_button_down(&mut self, _ctx: &mut Context, _state: &mut GameState, _button: MouseButton, _x: f32, _y: f32) -> GameResult {
+        Ok(())
+    }
+
+    fn mouse_button_up(&mut self, _ctx: &mut Context, _state: &mut GameState, _button: MouseButton, _x: f32, _y: f32) -> GameResult {
+        Ok(())
+    }
+
+    fn mouse_motion(&mut self, _ctx: &mut Context, _state: &mut GameState, _x: f32, _y: f32, _dx: f32, _dy: f32) -> GameResult {
+        Ok(())
+    }
+
+    fn mouse_wheel(&mut self, _ctx: &mut Context, _state: &mut GameState, _x: f32, _y: f32) -> GameResult {
+        Ok(())
+    }
+
+    fn key_down(&mut self, _ctx: &mut Context, _state: &mut GameState, _input: KeyInput, _repeated: bool) -> GameResult {
+        Ok(())
+    }
+
+    fn key_up(&mut self, _ctx: &mut Context, _state: &mut GameState, _input: KeyInput) -> GameResult {
+        Ok(())
+    }
+
+    fn text_input(&mut self, _ctx: &mut Context, _state: &mut GameState, _character: char) -> GameResult {
+        Ok(())
+    }
+}
+
+enum SceneFade {
+    Idle,
+    Out { easing: Easing<f32>, action: SceneAction },
+    In { easing: Easing<f32> },
+}
+
+impl SceneFade {
+    fn fade_out(action: SceneAction) -> Self {
+        let mut easing = Easing::new(0., 1., SCENE_FADE_TIME);
+        easing.run();
+        SceneFade::Out { easing, action }
+    }
+
+    fn fade_in() -> Self {
+        let mut easing = Easing::new(1., 0., SCENE_FADE_TIME);
+        easing.run();
+        SceneFade::In { easing }
+    }
+
+    fn alpha(&self) -> f32 {
+        match self {
+            SceneFade::Idle => 0.,
+            SceneFade::Out { easing, .. } | SceneFade::In { easing } => easing.now(),
+        }
+    }
+}
+
+/// 场景栈, 事件只转发给栈顶场景, 切换时淡出 -> 切换 -> 淡入
+pub struct SceneManager {
+    scenes: Vec<Box<dyn Scene>>,
+    state: GameState,
+    cache: ImageCache,
+    fade: SceneFade,
+}
+
+impl SceneManager {
+    pub fn new(ctx: &mut Context, base_dir: &Path, first: impl FnOnce(&GameState) -> Box<dyn Scene>) -> Self {
+        let state = GameState::new(base_dir, ctx);
+        info!("state: {:?}", state);
+        let mut manager = Self {
+            scenes: Vec::new(),
+            cache: ImageCache::new(base_dir.join("data")),
+            fade: SceneFade::fade_in(),
+            state,
+        };
+        let scene = first(&manager.state);
+        manager.apply(ctx, SceneAction::Push(scene));
+        manager
+    }
+
+    pub fn current(&self) -> Option<SceneEnum> {
+        self.scenes.last().map(|s| s.kind())
+    }
+
+    /// 请求切换场景, 正在切换时忽略
+    pub fn request(&mut self, action: SceneAction) {
+        if let SceneAction::None = action {
+            return;
+        }
+        if let SceneFade::Out { .. } = self.fade {
+            warn!("场景切换中, 忽略新的切换请求");
+            return;
+        }
+        self.fade = SceneFade::fade_out(action);
+    }
+
+    fn apply(&mut self, ctx: &mut Context, action: SceneAction) {
+        let (state, cache) = (&mut self.state, &mut self.cache);
+        match action {
+            SceneAction::None => {}
+            SceneAction::Push(mut scene) => {
+                if let Some(top) = self.scenes.last_mut() {
+                    top.leave(ctx, state, cache);
+                }
+                scene.resize(ctx, state);
+                scene.enter(ctx, state, cache);
+                self.scenes.push(scene);
+            }
+            SceneAction::Pop => {
+                if let Some(mut top) = self.scenes.pop() {
+                    top.leave(ctx, state, cache);
+                }
+                match self.scenes.last_mut() {
+                    Some(top) => {
+                        top.resize(ctx, state);
+                        top.enter(ctx, state, cache);
+                    }
+                    None => ctx.request_quit(),
+                }
+            }
+            SceneAction::Replace(mut scene) => {
+                if let Some(mut top) = self.scenes.pop() {
+                    top.leave(ctx, state, cache);
+                }
+                scene.resize(ctx, state);
+                scene.enter(ctx, state, cache);
+                self.scenes.push(scene);
+            }
+            SceneAction::Quit => ctx.request_quit(),
+        }
+        info!("当前场景: {:?}", self.current());
+    }
+}
+
+impl EventHandler<GameError> for SceneManager {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        let delta = ctx.time.delta().as_secs_f64();
+        self.fade = match std::mem::replace(&mut self.fade, SceneFade::Idle) {
+            SceneFade::Idle => SceneFade::Idle,
+            SceneFade::Out { mut easing, action } => {
+                if easing.advance(delta) {
+                    self.apply(ctx, action);
+                    SceneFade::fade_in()
+                } else {
+                    SceneFade::Out { easing, action }
+                }
+            }
+            SceneFade::In { mut easing } => {
+                if easing.advance(delta) {
+                    SceneFade::Idle
+                } else {
+                    SceneFade::In { easing }
+                }
+            }
+        };
+        let action = match self.scenes.last_mut() {
+            Some(scene) => scene.update(ctx, &mut self.state, &mut self.cache)?,
+            None => SceneAction::None,
+        };
+        self.request(action);
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let mut canvas = Canvas::from_frame(ctx, Color::BLACK);
+        // 从最上层的非覆盖场景开始往上画
+        let start = self.scenes.iter().rposition(|s| !s.is_overlay()).unwrap_or(0);
+        for scene in self.scenes[start..].iter_mut() {
+            scene.draw(ctx, &mut canvas, &mut self.state, &mut self.cache)?;
+        }
+        let alpha = self.fade.alpha();
+        if alpha > 0. {
+            canvas.draw(&Quad, DrawParam::default().dest(vec2(0., 0.)).scale(vec2(self.state.window_size.0, self.state.window_size.1)).color(Color::new(0., 0., 0., alpha)));
+        }
+        ctx.gfx.set_window_title(&format!("D32 - {:.0} FPS", ctx.time.fps()));
+        canvas.finish(ctx)
+    }
+
+    fn mouse_button_down_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) -> GameResult {
+        let state = &mut self.state;
+        match self.scenes.last_mut().filter(|_| matches!(self.fade, SceneFade::Idle)) {
+            Some(scene) => scene.mouse_button_down(ctx, state, button, x, y),
+            None => Ok(()),
+        }
+    }
+
+    fn mouse_button_up_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) -> GameResult {
+        let state = &mut self.state;
+        match self.scenes.last_mut().filter(|_| matches!(self.fade, SceneFade::Idle)) {
+            Some(scene) => scene.mouse_button_up(ctx, state, button, x, y),
+            None => Ok(()),
+        }
+    }
+
+    fn mouse_motion_event(&mut self, ctx: &mut Context, x: f32, y: f32, dx: f32, dy: f32) -> GameResult {
+        let state = &mut self.state;
+        match self.scenes.last_mut().filter(|_| matches!(self.fade, SceneFade::Idle)) {
+            Some(scene) => scene.mouse_motion(ctx, state, x, y, dx, dy),
+            None => Ok(()),
+        }
+    }
+
+    fn mouse_wheel_event(&mut self, ctx: &mut Context, x: f32, y: f32) -> GameResult {
+        let state = &mut self.state;
+        match self.scenes.last_mut().filter(|_| matches!(self.fade, SceneFade::Idle)) {
+            Some(scene) => scene.mouse_wheel(ctx, state, x, y),
+            None => Ok(()),
+        }
+    }
+
+    fn key_down_event(&mut self, ctx: &mut Context, input: KeyInput, repeated: bool) -> GameResult {
+        let state = &mut self.state;
+        match self.scenes.last_mut().filter(|_| matches!(self.fade, SceneFade::Idle)) {
+            Some(scene) => scene.key_down(ctx, state, input, repeated),
+            None => Ok(()),
+        }
+    }
+
+    fn key_up_event(&mut self, ctx: &mut Context, input: KeyInput) -> GameResult {
+        let state = &mut self.state;
+        match self.scenes.last_mut().filter(|_| matches!(self.fade, SceneFade::Idle)) {
+            Some(scene) => scene.key_up(ctx, state, input),
+            None => Ok(()),
+        }
+    }
+
+    fn text_input_event(&mut self, ctx: &mut Context, character: char) -> GameResult {
+        let state = &mut self.state;
+        match self.scenes.last_mut().filter(|_| matches!(self.fade, SceneFade::Idle)) {
+            Some(scene) => scene.text_input(ctx, state, character),
+            None => Ok(()),
+        }
+    }
+
+    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) -> GameResult {
+        self.state.resize(ctx, width, height);
+        for scene in self.scenes.iter_mut() {
+            scene.resize(ctx, &self.state);
+        }
+        info!("resize: {:?}", self.state);
+        Ok(())
+    }
+}
@@ -0,0 +1,114 @@
+use ggez::{Context, GameResult};
+use ggez::event::MouseButton;
+use ggez::graphics::Canvas;
+use ggez::input::keyboard::{KeyCode, KeyInput, KeyMods};
+use tracing::info;
+use crate::cache::ImageCache;
+use crate::control::GameState;
+use crate::control::map::MapControl;
+use crate::draw::actor::ActorKind;
+use crate::scene::{Scene, SceneAction, SceneEnum};
+
+/// 调试用: F5 朝光标下的格子施放的效果, F6 在玩家旁边放的怪物外观, F7 切换光标下角色或玩家的中毒状态
+const DEBUG_EFFECT: u32 = 1;
+const DEBUG_MONSTER: u32 = 1;
+
+/// 游戏场景: 地图和玩家
+pub struct PlayerScene {
+    map: MapControl,
+    /// 放怪物需要 ImageCache, 按键时记下, 在 update 中处理
+    spawn_monster: bool,
+}
+
+impl PlayerScene {
+    pub fn new(state: &GameState) -> GameResult<Self> {
+        Ok(Self { map: MapControl::new(state)?, spawn_monster: false })
+    }
+}
+
+impl Scene for PlayerScene {
+    fn kind(&self) -> SceneEnum {
+        SceneEnum::Player
+    }
+
+    fn update(&mut self, ctx: &mut Context, _state: &mut GameState, cache: &mut ImageCache) -> GameResult<SceneAction> {
+        self.map.update(ctx, cache);
+        if std::mem::take(&mut self.spawn_monster) {
+            let (tile_x, tile_y) = self.map.player_tile();
+            self.map.spawn_actor(cache, ActorKind::Monster, DEBUG_MONSTER, tile_x + 2, tile_y, 6);
+        }
+        while let Some(event) = self.map.poll_event() {
+            info!("地图事件: {:?}", event);
+        }
+        Ok(SceneAction::None)
+    }
+
+    fn draw(&mut self, ctx: &mut Context, canvas: &mut Canvas, state: &mut GameState, cache: &mut ImageCache) -> GameResult {
+        self.map.draw(ctx, canvas, state, cache);
+        Ok(())
+    }
+
+    fn resize(&mut self, _ctx: &mut Context, state: &GameState) {
+        self.map.resize(state);
+    }
+
+    /// Shift + 左键原地攻击, 不走动
+    fn mouse_button_down(&mut self, ctx: &mut Context, _state: &mut GameState, button: MouseButton, x: f32, y: f32) -> GameResult {
+        if button == MouseButton::Left && ctx.keyboard.is_mod_active(KeyMods::SHIFT) {
+            let (tile_x, tile_y) = self.map.tile_at(x, y);
+            self.map.attack(tile_x, tile_y);
+        } else {
+            self.map.mouse_button_down(button, x, y);
+        }
+        Ok(())
+    }
+
+    fn mouse_button_up(&mut self, _ctx: &mut Context, _state: &mut GameState, button: MouseButton, x: f32, y: f32) -> GameResult {
+        self.map.mouse_button_up(button, x, y);
+        Ok(())
+    }
+
+    fn mouse_motion(&mut self, _ctx: &mut Context, _state: &mut GameState, x: f32, y: f32, _dx: f32, _dy: f32) -> GameResult {
+        self.map.debug().set_mouse(x, y);
+        Ok(())
+    }
+
+    fn mouse_wheel(&mut self, _ctx: &mut Context, _state: &mut GameState, _x: f32, y: f32) -> GameResult {
+        if y != 0. {
+            self.map.zoom(y.signum() as i32);
+        }
+        Ok(())
+    }
+
+    fn key_down(&mut self, ctx: &mut Context, _state: &mut GameState, input: KeyInput, _repeated: bool) -> GameResult {
+        match input.keycode {
+            Some(KeyCode::F3) => self.map.debug().toggle(),
+            Some(KeyCode::F4) => self.map.debug().toggle_coordinates(),
+            Some(KeyCode::Tab) => self.map.toggle_world_map(),
+            Some(KeyCode::F5) => {
+                let mouse = ctx.mouse.position();
+                let (tile_x, tile_y) = self.map.tile_at(mouse.x, mouse.y);
+                self.map.cast(DEBUG_EFFECT, tile_x, tile_y);
+            }
+            Some(KeyCode::F6) => self.spawn_monster = true,
+            Some(KeyCode::F7) => {
+                let mouse = ctx.mouse.position();
+                let (tile_x, tile_y) = self.map.tile_at(mouse.x, mouse.y);
+                self.map.cycle_poison(tile_x, tile_y);
+            }
+            Some(KeyCode::F8) => self.map.enter_next_map(),
+            Some(KeyCode::F9) => {
+                let mouse = ctx.mouse.position();
+                let (tile_x, tile_y) = self.map.tile_at(mouse.x, mouse.y);
+                self.map.toggle_door(tile_x, tile_y);
+            }
+            Some(KeyCode::F10) => {
+                let mouse = ctx.mouse.position();
+                let (tile_x, tile_y) = self.map.tile_at(mouse.x, mouse.y);
+                self.map.kill_actor(tile_x, tile_y);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,384 @@
+use ggez::{Context, GameResult};
+use ggez::event::MouseButton;
+use ggez::glam::vec2;
+use ggez::graphics::{Canvas, Color, DrawMode, DrawParam, Mesh, Quad, Rect};
+use ggez::input::keyboard::{KeyCode, KeyInput};
+use tracing::{error, info};
+use crate::account::{self, AccountService, CharacterInfo, Gender, Job, RoleRequest, RoleResponse};
+use crate::cache::ImageCache;
+use crate::control::GameState;
+use crate::draw::frame::{self, PRGUSE_FILE_ID};
+use crate::draw::player::{PlayerAction, PlayerFrame, PlayerLook, PlayerSprite};
+use crate::draw::text::TextStyle;
+use crate::scene::player::PlayerScene;
+use crate::scene::{Scene, SceneAction, SceneEnum};
+use crate::ui::{Anchor, Layout, Ui, UiEvent, Widget, WidgetId};
+
+pub const DEFAULT_MAX_CHARACTERS: usize = 3;
+const IDLE_FRAME_TIME: f64 = 0.5;
+/// 面向屏幕的方向(正下)
+const PREVIEW_DIRECTION: u8 = 4;
+const NAME_MAX_LEN: usize = 7;
+/// Prguse 中按钮按下时的图片
+const START_BUTTON_INDEX: u32 = 68;
+const NEW_ROLE_BUTTON_INDEX: u32 = 69;
+const DELETE_BUTTON_INDEX: u32 = 70;
+const EXIT_BUTTON_INDEX: u32 = 72;
+const OK_BUTTON_INDEX: u32 = 62;
+const CLOSE_BUTTON_INDEX: u32 = 64;
+
+const SLOT_SIZE: (f32, f32) = (200., 260.);
+const PANEL_SIZE: (f32, f32) = (360., 320.);
+const SLOT_COLOR: Color = Color::new(0., 0., 0., 0.5);
+const SELECTED_COLOR: Color = Color::new(0.9, 0.75, 0.3, 1.);
+const ERROR_COLOR: Color = Color::new(1., 0.4, 0.3, 1.);
+const CLEAR_COLOR: Color = Color::new(0., 0., 0., 0.);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Select,
+    Create,
+    ConfirmDelete,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Button {
+    Slot(usize),
+    Start,
+    Create,
+    Delete,
+    Back,
+    Submit,
+    Cancel,
+}
+
+/// 选择角色场景: 角色列表, 创建, 删除, 开始游戏
+pub struct RoleScene {
+    service: Box<dyn AccountService>,
+    max_characters: usize,
+    characters: Vec<CharacterInfo>,
+    selected: usize,
+    mode: Mode,
+    job: Job,
+    gender: Gender,
+    waiting: bool,
+    time: f64,
+    /// 角色预览, 所有角色共用, 画之前换外观
+    preview: PlayerSprite,
+    next: Option<SceneAction>,
+    ui: Ui,
+    /// 底部按钮栏和创建角色面板
+    bar: WidgetId,
+    panel: WidgetId,
+    name: WidgetId,
+    message: WidgetId,
+    buttons: Vec<(WidgetId, Button)>,
+    jobs: Vec<(WidgetId, Job)>,
+    genders: Vec<(WidgetId, Gender)>,
+}
+
+impl RoleScene {
+    pub fn new(state: &GameState, service: Box<dyn AccountService>, max_characters: usize) -> Self {
+        let mut ui = Ui::new(state.window_size.0, state.window_size.1);
+        let mut buttons = Vec::new();
+        let bar = ui.add(Widget::window(390., 26.).anchor(Anchor::Center).at(0., SLOT_SIZE.1 / 2. + 13.).color(CLEAR_COLOR)
+            .layout(Layout::Horizontal { padding: 0., spacing: 10. }));
+        for (button, image, label) in [
+            (Button::Start, START_BUTTON_INDEX, "开始游戏"),
+            (Button::Create, NEW_ROLE_BUTTON_INDEX, "新建角色"),
+            (Button::Delete, DELETE_BUTTON_INDEX, "删除角色"),
+            (Button::Back, EXIT_BUTTON_INDEX, "返回"),
+        ] {
+            buttons.push((ui.add_child(bar, Widget::button(Rect::new(0., 0., 90., 26.), label).pressed_frame(frame::frame_key(PRGUSE_FILE_ID, image))), button));
+        }
+        let message = ui.add(Widget::label(Rect::new(0., SLOT_SIZE.1 / 2. + 50., 390., 20.), "").anchor(Anchor::Center).color(ERROR_COLOR));
+
+        let panel = ui.add(Widget::window(PANEL_SIZE.0, PANEL_SIZE.1).anchor(Anchor::Center).title("新建角色").draggable().hidden());
+        ui.add_child(panel, Widget::label(Rect::new(20., 33., 60., 16.), "角色名"));
+        let name = ui.add_child(panel, Widget::input(Rect::new(80., 30., 180., 22.), NAME_MAX_LEN * 2));
+        let jobs = [Job::Warrior, Job::Wizard, Job::Taoist].into_iter().enumerate()
+            .map(|(i, job)| (ui.add_child(panel, Widget::checkbox(Rect::new(20. + i as f32 * 70., 80., 60., 24.), job.name(), job == Job::Warrior)), job))
+            .collect();
+        let genders = [Gender::Male, Gender::Female].into_iter().enumerate()
+            .map(|(i, gender)| (ui.add_child(panel, Widget::checkbox(Rect::new(20. + i as f32 * 70., 120., 60., 24.), gender.name(), gender == Gender::Male)), gender))
+            .collect();
+        buttons.push((ui.add_child(panel, Widget::button(Rect::new(90., PANEL_SIZE.1 - 40., 80., 24.), "确定").pressed_frame(frame::frame_key(PRGUSE_FILE_ID, OK_BUTTON_INDEX))), Button::Submit));
+        buttons.push((ui.add_child(panel, Widget::button(Rect::new(190., PANEL_SIZE.1 - 40., 80., 24.), "取消").pressed_frame(frame::frame_key(PRGUSE_FILE_ID, CLOSE_BUTTON_INDEX))), Button::Cancel));
+        Self {
+            service,
+            max_characters,
+            characters: Vec::new(),
+            selected: 0,
+            mode: Mode::Select,
+            job: Job::Warrior,
+            gender: Gender::Male,
+            waiting: false,
+            time: 0.,
+            preview: PlayerSprite::new(PlayerLook::new(0, 1, 0, Gender::Male)),
+            next: None,
+            ui,
+            bar,
+            panel,
+            name,
+            message,
+            buttons,
+            jobs,
+            genders,
+        }
+    }
+
+    fn set_mode(&mut self, mode: Mode) {
+        self.mode = mode;
+        self.ui.set_visible(self.panel, mode == Mode::Create);
+        self.ui.set_visible(self.bar, mode != Mode::Create);
+        if mode == Mode::Create {
+            self.ui.set_text(self.name, "");
+            self.ui.focus(self.name);
+        }
+    }
+
+    fn set_message(&mut self, message: &str) {
+        self.ui.set_text(self.message, message);
+    }
+
+    /// 职业和性别的复选框作为单选使用
+    fn toggle(&mut self, id: WidgetId) {
+        if let Some((_, job)) = self.jobs.iter().find(|(j, _)| *j == id) {
+            self.job = *job;
+        }
+        if let Some((_, gender)) = self.genders.iter().find(|(g, _)| *g == id) {
+            self.gender = *gender;
+        }
+        for (id, job) in self.jobs.iter() {
+            self.ui.set_checked(*id, *job == self.job);
+        }
+        for (id, gender) in self.genders.iter() {
+            self.ui.set_checked(*id, *gender == self.gender);
+        }
+    }
+
+    fn send(&mut self, state: &GameState, request: RoleRequest) {
+        match &state.session {
+            Some(session) => {
+                self.waiting = true;
+                self.service.send(session, request);
+            }
+            None => error!("未登录, 无法请求角色服务"),
+        }
+    }
+
+    fn handle_response(&mut self, state: &mut GameState, response: RoleResponse) {
+        self.waiting = false;
+        match response {
+            RoleResponse::List(Ok(list)) => {
+                self.characters = list.into_iter().take(self.max_characters).collect();
+                self.selected = self.selected.min(self.characters.len().saturating_sub(1));
+            }
+            RoleResponse::Create(Ok(info)) => {
+                info!("创建角色: {}", info.name);
+                self.characters.push(info);
+                self.selected = self.characters.len() - 1;
+                self.set_mode(Mode::Select);
+                self.set_message("");
+            }
+            RoleResponse::Delete(Ok(name)) => {
+                self.characters.retain(|c| c.name != name);
+                self.selected = self.selected.min(self.characters.len().saturating_sub(1));
+                self.set_mode(Mode::Select);
+                self.set_message(&format!("已删除角色: {}", name));
+            }
+            RoleResponse::Start(Ok(name)) => {
+                info!("进入游戏: {}", name);
+                if let Some(session) = state.session.as_mut() {
+                    session.character = self.characters.iter().find(|c| c.name == name).cloned();
+                }
+                match PlayerScene::new(state) {
+                    Ok(scene) => self.next = Some(SceneAction::Replace(Box::new(scene))),
+                    Err(e) => {
+                        error!("进入游戏失败: {:?}", e);
+                        self.set_message("进入游戏失败");
+                    }
+                }
+            }
+            RoleResponse::List(Err(e)) | RoleResponse::Create(Err(e)) | RoleResponse::Delete(Err(e)) | RoleResponse::Start(Err(e)) => self.set_message(&e),
+        }
+    }
+
+    fn press(&mut self, state: &GameState, button: Button) {
+        if self.waiting {
+            return;
+        }
+        match button {
+            Button::Slot(i) => {
+                self.selected = i;
+                if self.mode == Mode::ConfirmDelete {
+                    self.set_mode(Mode::Select);
+                }
+            }
+            Button::Start => match self.characters.get(self.selected) {
+                Some(c) => {
+                    let name = c.name.clone();
+                    self.send(state, RoleRequest::Start { name });
+                }
+                None => self.set_message("请先创建角色"),
+            },
+            Button::Create => {
+                if self.characters.len() >= self.max_characters {
+                    self.set_message("角色数量已满");
+                } else {
+                    self.set_mode(Mode::Create);
+                    self.set_message("");
+                }
+            }
+            Button::Delete => match (self.mode, self.characters.get(self.selected)) {
+                (_, None) => {}
+                (Mode::ConfirmDelete, Some(c)) => {
+                    let name = c.name.clone();
+                    self.send(state, RoleRequest::Delete { name });
+                }
+                (_, Some(c)) => {
+                    let message = format!("再次点击删除确认删除: {}", c.name);
+                    self.set_message(&message);
+                    self.set_mode(Mode::ConfirmDelete);
+                }
+            },
+            Button::Back => self.next = Some(SceneAction::Pop),
+            Button::Submit => {
+                let name = String::from(self.ui.text(self.name));
+                match account::validate_character_name(&name) {
+                    Ok(_) => {
+                        let request = RoleRequest::Create { name, job: self.job, gender: self.gender };
+                        self.send(state, request);
+                    }
+                    Err(e) => self.set_message(&e),
+                }
+            }
+            Button::Cancel => {
+                self.set_mode(Mode::Select);
+                self.set_message("");
+            }
+        }
+    }
+
+    fn slot_rect(&self, state: &GameState, i: usize) -> Rect {
+        let total = self.max_characters as f32 * SLOT_SIZE.0;
+        let x = (state.window_size.0 - total) / 2. + i as f32 * SLOT_SIZE.0;
+        Rect::new(x + 10., state.window_size.1 / 2. - SLOT_SIZE.1 / 2. - 40., SLOT_SIZE.0 - 20., SLOT_SIZE.1)
+    }
+
+    fn draw_character(&mut self, ctx: &mut Context, canvas: &mut Canvas, cache: &mut ImageCache, look: PlayerLook, x: f32, y: f32) {
+        self.preview.set_look(look);
+        self.preview.load(cache, PlayerAction::Stand);
+        let frame = (self.time / IDLE_FRAME_TIME) as u32;
+        for key in self.preview.keys(PlayerFrame::new(PlayerAction::Stand, PREVIEW_DIRECTION, frame)) {
+            frame::draw_sprite(ctx, canvas, cache, key, x, y, Color::WHITE);
+        }
+    }
+}
+
+impl Scene for RoleScene {
+    fn kind(&self) -> SceneEnum {
+        SceneEnum::Role
+    }
+
+    fn enter(&mut self, _ctx: &mut Context, state: &mut GameState, cache: &mut ImageCache) {
+        self.set_mode(Mode::Select);
+        self.ui.resize(state.window_size.0, state.window_size.1);
+        cache.load_keys(&[START_BUTTON_INDEX, NEW_ROLE_BUTTON_INDEX, DELETE_BUTTON_INDEX, EXIT_BUTTON_INDEX, OK_BUTTON_INDEX, CLOSE_BUTTON_INDEX]
+            .map(|index| frame::frame_key(PRGUSE_FILE_ID, index)));
+        self.send(state, RoleRequest::List);
+    }
+
+    fn update(&mut self, ctx: &mut Context, state: &mut GameState, _cache: &mut ImageCache) -> GameResult<SceneAction> {
+        self.time += ctx.time.delta().as_secs_f64();
+        while let Some(event) = self.ui.poll_event() {
+            match event {
+                UiEvent::Clicked(id) => if let Some((_, button)) = self.buttons.iter().find(|(b, _)| *b == id) {
+                    let button = *button;
+                    self.press(state, button);
+                },
+                UiEvent::Toggled(id, _) => self.toggle(id),
+                UiEvent::Submitted(_) => self.press(state, Button::Submit),
+                _ => {}
+            }
+        }
+        while let Some(response) = self.service.poll() {
+            self.handle_response(state, response);
+        }
+        Ok(self.next.take().unwrap_or(SceneAction::None))
+    }
+
+    fn draw(&mut self, ctx: &mut Context, canvas: &mut Canvas, state: &mut GameState, cache: &mut ImageCache) -> GameResult {
+        for i in 0..self.max_characters {
+            let rect = self.slot_rect(state, i);
+            canvas.draw(&Quad, DrawParam::default().dest(rect.point()).scale(rect.size()).color(SLOT_COLOR));
+            if i == self.selected && self.mode != Mode::Create {
+                canvas.draw(&Mesh::new_rectangle(ctx, DrawMode::stroke(2.), rect, SELECTED_COLOR)?, DrawParam::default());
+            }
+            if let Some(c) = self.characters.get(i).cloned() {
+                self.draw_character(ctx, canvas, cache, PlayerLook::new(c.dress, 1, 0, c.gender), rect.x + rect.w / 2., rect.y + rect.h - 70.);
+                let info = format!("{}\n{} {} Lv.{}", c.name, c.job.name(), c.gender.name(), c.level);
+                state.text.draw_centered(ctx, canvas, &info, Rect::new(rect.x, rect.y + rect.h - 50., rect.w, 40.), TextStyle::new(14., Color::WHITE).shadow());
+            }
+        }
+
+        self.ui.draw(ctx, canvas, cache, &mut state.text)?;
+        if self.mode == Mode::Create {
+            let panel = self.ui.bounds(self.panel);
+            let gender = self.gender;
+            self.draw_character(ctx, canvas, cache, PlayerLook::new(0, 1, 0, gender), panel.x + panel.w - 70., panel.y + 230.);
+        }
+        Ok(())
+    }
+
+    fn resize(&mut self, _ctx: &mut Context, state: &GameState) {
+        self.ui.resize(state.window_size.0, state.window_size.1);
+    }
+
+    fn mouse_button_down(&mut self, _ctx: &mut Context, state: &mut GameState, button: MouseButton, x: f32, y: f32) -> GameResult {
+        if self.ui.mouse_button_down(button, x, y) || button != MouseButton::Left || self.mode == Mode::Create {
+            return Ok(());
+        }
+        if let Some(i) = (0..self.max_characters).find(|i| self.slot_rect(state, *i).contains(vec2(x, y))) {
+            self.press(state, Button::Slot(i));
+        }
+        Ok(())
+    }
+
+    fn mouse_button_up(&mut self, _ctx: &mut Context, _state: &mut GameState, button: MouseButton, x: f32, y: f32) -> GameResult {
+        self.ui.mouse_button_up(button, x, y);
+        Ok(())
+    }
+
+    fn mouse_motion(&mut self, _ctx: &mut Context, _state: &mut GameState, x: f32, y: f32, _dx: f32, _dy: f32) -> GameResult {
+        self.ui.mouse_motion(x, y);
+        Ok(())
+    }
+
+    fn mouse_wheel(&mut self, _ctx: &mut Context, _state: &mut GameState, x: f32, y: f32) -> GameResult {
+        self.ui.mouse_wheel(x, y);
+        Ok(())
+    }
+
+    fn key_down(&mut self, _ctx: &mut Context, state: &mut GameState, input: KeyInput, _repeated: bool) -> GameResult {
+        if self.ui.key_down(input) {
+            return Ok(());
+        }
+        match (self.mode, input.keycode) {
+            (Mode::Create, Some(KeyCode::Escape)) => self.press(state, Button::Cancel),
+            (Mode::Create, _) => {}
+            (_, Some(KeyCode::Left)) if self.selected > 0 => self.press(state, Button::Slot(self.selected - 1)),
+            (_, Some(KeyCode::Right)) if self.selected + 1 < self.characters.len() => self.press(state, Button::Slot(self.selected + 1)),
+            (_, Some(KeyCode::Return)) => self.press(state, Button::Start),
+            (Mode::ConfirmDelete, Some(KeyCode::Escape)) => self.press(state, Button::Cancel),
+            (_, Some(KeyCode::Escape)) => self.press(state, Button::Back),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn text_input(&mut self, _ctx: &mut Context, _state: &mut GameState, character: char) -> GameResult {
+        self.ui.text_input(character);
+        Ok(())
+    }
+}
@@ -1,30 +1,28 @@
 use std::ops::Deref;
-use std::path::PathBuf;
+use std::path::Path;
 use std::time::Instant;
 use ggez::event::{EventHandler, MouseButton};
-use ggez::{Context, GameError};
+use ggez::input::keyboard::{KeyCode, KeyInput};
+use ggez::{Context, GameError, GameResult};
 use ggez::graphics::{Canvas, Color, DrawParam};
 use tracing::info;
 use crate::cache;
 use crate::cache::ImageCache;
 use crate::control::GameState;
+use crate::control::map::MapControl;
 use crate::draw;
-use crate::draw::map::MapDraw;
 // use crate::cache_1::ImageCacheManager;
 
 pub struct TestCacheApp {
-    map_layer: MapDraw,
+    map_layer: MapControl,
     state: GameState,
     cache: ImageCache
 }
 
 impl TestCacheApp {
-    pub fn new(path: &PathBuf, ctx: &mut Context) -> Self {
+    pub fn new(path: &Path, ctx: &mut Context) -> GameResult<Self> {
         // ctx.fs.resources_dir()
-        let scale_factor = ctx.gfx.window().scale_factor();
         // let size = ctx.gfx.window().inner_size();
-        let (draw_width, draw_height) = ctx.gfx.drawable_size();
-        let monitor_size = ctx.gfx.window().current_monitor().unwrap().size();
         // println!("dw: {}, dh: {}", draw_width, draw_height);
         // println!("monitor_size: {:?}", monitor_size);
         // println!("size: {:?}, scale_factor: {}", size, scale_factor);
@@ -37,27 +35,19 @@ impl TestCacheApp {
         // println!("{:?}", ctx.fs.user_data_dir());
         // println!("{:?}", ctx.fs.resources_dir());
 
-        let state = GameState {
-            scale_factor: scale_factor as f32,
-            base_dir: path.to_path_buf(),
-            screen_size: (monitor_size.width as f32, monitor_size.height as f32),
-            window_size: (draw_width, draw_height),
-            center_point: (draw_width / 2., draw_height / 2.),
-
-        };
+        let state = GameState::new(path, ctx);
         info!("state: {:?}", state);
-        let mut map = MapDraw::new(&path, 10, 1, "n3",draw_width, draw_height);
-        map.jump_by_tile(333, 333, 0, 0);
-        TestCacheApp {
-            map_layer: map,
+        Ok(TestCacheApp {
+            map_layer: MapControl::new(&state)?,
             cache: cache::ImageCache::new(path.join("data")),
             state,
-        }
+        })
     }
 }
 
 impl EventHandler<GameError> for TestCacheApp {
-    fn update(&mut self, _ctx: &mut Context) -> Result<(), GameError> {
+    fn update(&mut self, ctx: &mut Context) -> Result<(), GameError> {
+        self.map_layer.update(ctx, &mut self.cache);
         Ok(())
     }
 
@@ -77,8 +67,7 @@ impl EventHandler<GameError> for TestCacheApp {
         // println!("inst: {:?}", now.elapsed());
         let mut canvas = Canvas::from_frame(ctx, Color::new(0.1, 0.2, 0.3, 1.0));
         //     canvas.draw(&img.image(), DrawParam::default());
-        self.map_layer.draw_tile(&mut canvas, ctx, &mut self.cache, 0x1FF);
-        self.map_layer.draw_objects(ctx, &mut canvas, &mut self.cache);
+        self.map_layer.draw(ctx, &mut canvas, &mut self.state, &mut self.cache);
 
         ctx.gfx.set_window_title(&format!(
             "D32 - {:.0} FPS",
@@ -90,26 +79,29 @@ impl EventHandler<GameError> for TestCacheApp {
         // Err(GameError::ConfigError(String::new()))
     }
 
-    fn mouse_button_down_event(&mut self, _ctx: &mut Context, _button: MouseButton, _x: f32, _y: f32) -> Result<(), GameError> {
+    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) -> Result<(), GameError> {
+        self.state.resize(ctx, width, height);
+        self.map_layer.resize(&self.state);
+        info!("resize: {:?}", self.state);
+        Ok(())
+    }
 
-        let angle = angle2(self.state.center_point.0, self.state.center_point.1, _x, _y);
-        let sharing = sharing2(angle, 8.0);
-        // let angle2 = angle + if angle < 0. {  360.0 } else { 0. };
-        // println!("first: {}", angle2);
-        // let angle2 = angle2 + 90.0 + 360.0 / 2.0 / 8.0;
-        // println!("sharing: {}, angle: {}, an2: {}", sharing, angle, angle2);
-        
+    fn mouse_motion_event(&mut self, _ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) -> Result<(), GameError> {
+        self.map_layer.debug().set_mouse(x, y);
         Ok(())
     }
-}
 
-fn angle2(src_x: f32, src_y: f32, dst_x: f32, dst_y: f32) -> f32 {
-    (dst_y - src_y).atan2(dst_x - src_x) * 57.295776
-}
+    fn key_down_event(&mut self, _ctx: &mut Context, input: KeyInput, _repeated: bool) -> Result<(), GameError> {
+        match input.keycode {
+            Some(KeyCode::F3) => self.map_layer.debug().toggle(),
+            Some(KeyCode::F4) => self.map_layer.debug().toggle_coordinates(),
+            _ => {}
+        }
+        Ok(())
+    }
 
-fn sharing2(angle: f32, sharing: f32) -> f32 {
-    // let s = 90.0 + 360.0 / 2.0 / sharing as f32;
-    let angle = angle + 90.0 + 360.0 / 2.0 / sharing;
-    let angle = angle + if angle < 0. { 360.0 } else { 0. };
-    ((angle) / (360. / sharing) + 1.0).floor()
-}
\ No newline at end of file
+    fn mouse_button_down_event(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) -> Result<(), GameError> {
+        self.map_layer.mouse_button_down(button, x, y);
+        Ok(())
+    }
+}
@@ -0,0 +1,589 @@
+use std::collections::{HashMap, VecDeque};
+use ggez::{Context, GameResult};
+use ggez::event::MouseButton;
+use ggez::glam::vec2;
+use ggez::graphics::{Canvas, Color, DrawMode, DrawParam, Mesh, Quad, Rect};
+use ggez::input::keyboard::{KeyCode, KeyInput};
+use crate::cache::ImageCache;
+use crate::draw::frame;
+use crate::draw::text::{TextEffect, TextRenderer, TextStyle};
+
+pub mod widget;
+
+pub use widget::{Anchor, Layout, Widget, WidgetId, WidgetKind};
+
+const PANEL_COLOR: Color = Color::new(0.08, 0.06, 0.04, 0.9);
+const BUTTON_COLOR: Color = Color::new(0.3, 0.22, 0.12, 1.);
+const HOVER_COLOR: Color = Color::new(0.42, 0.32, 0.18, 1.);
+const PRESSED_COLOR: Color = Color::new(0.2, 0.14, 0.08, 1.);
+const INPUT_COLOR: Color = Color::new(0., 0., 0., 0.8);
+const FOCUS_COLOR: Color = Color::new(0.9, 0.75, 0.3, 1.);
+const SELECTED_COLOR: Color = Color::new(0.5, 0.38, 0.15, 0.8);
+const SCROLL_MIN_THUMB: f32 = 16.;
+
+/// 控件产生的事件, 场景在 update 中取出处理
+#[derive(Debug, Clone, PartialEq)]
+pub enum UiEvent {
+    Clicked(WidgetId),
+    /// 输入框内容或滚动条位置变化
+    Changed(WidgetId),
+    /// 输入框中按回车
+    Submitted(WidgetId),
+    Selected(WidgetId, usize),
+    Toggled(WidgetId, bool),
+}
+
+/// 保留模式的界面: 控件树, 布局, 焦点, 层级和拖动
+#[derive(Default)]
+pub struct Ui {
+    widgets: HashMap<WidgetId, Widget>,
+    next_id: WidgetId,
+    /// 顶层控件, 后面的在上层
+    roots: Vec<WidgetId>,
+    focus: Option<WidgetId>,
+    hover: Option<WidgetId>,
+    pressed: Option<WidgetId>,
+    /// 拖动的窗口和按下时鼠标相对窗口的位置
+    drag: Option<(WidgetId, f32, f32)>,
+    mouse: (f32, f32),
+    window_size: (f32, f32),
+    events: VecDeque<UiEvent>,
+}
+
+impl Ui {
+    pub fn new(window_width: f32, window_height: f32) -> Self {
+        Self { window_size: (window_width, window_height), ..Default::default() }
+    }
+
+    pub fn resize(&mut self, window_width: f32, window_height: f32) {
+        self.window_size = (window_width, window_height);
+    }
+
+    /// 添加顶层控件, 放在最上层
+    pub fn add(&mut self, widget: Widget) -> WidgetId {
+        let id = self.insert(widget, None);
+        self.roots.push(id);
+        id
+    }
+
+    pub fn add_child(&mut self, parent: WidgetId, widget: Widget) -> WidgetId {
+        let id = self.insert(widget, Some(parent));
+        if let Some(p) = self.widgets.get_mut(&parent) {
+            p.children.push(id);
+        }
+        id
+    }
+
+    fn insert(&mut self, mut widget: Widget, parent: Option<WidgetId>) -> WidgetId {
+        let id = self.next_id;
+        self.next_id += 1;
+        widget.parent = parent;
+        self.widgets.insert(id, widget);
+        id
+    }
+
+    pub fn remove(&mut self, id: WidgetId) {
+        let widget = match self.widgets.remove(&id) {
+            Some(widget) => widget,
+            None => return,
+        };
+        for child in widget.children {
+            self.remove(child);
+        }
+        match widget.parent.and_then(|p| self.widgets.get_mut(&p)) {
+            Some(parent) => parent.children.retain(|c| *c != id),
+            None => self.roots.retain(|r| *r != id),
+        }
+        for state in [&mut self.focus, &mut self.hover, &mut self.pressed] {
+            if *state == Some(id) {
+                *state = None;
+            }
+        }
+        if self.drag.is_some_and(|(d, _, _)| d == id) {
+            self.drag = None;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        let roots = self.roots.clone();
+        roots.into_iter().for_each(|r| self.remove(r));
+        self.events.clear();
+    }
+
+    pub fn poll_event(&mut self) -> Option<UiEvent> {
+        self.events.pop_front()
+    }
+
+    pub fn set_visible(&mut self, id: WidgetId, visible: bool) {
+        if let Some(w) = self.widgets.get_mut(&id) {
+            w.visible = visible;
+        }
+        if !visible && self.focus.is_some_and(|f| self.is_ancestor(id, f)) {
+            self.focus = None;
+        }
+    }
+
+    fn is_ancestor(&self, ancestor: WidgetId, id: WidgetId) -> bool {
+        let mut node = Some(id);
+        while let Some(n) = node {
+            if n == ancestor {
+                return true;
+            }
+            node = self.widgets.get(&n).and_then(|w| w.parent);
+        }
+        false
+    }
+
+    fn root_of(&self, id: WidgetId) -> WidgetId {
+        let mut id = id;
+        while let Some(parent) = self.widgets.get(&id).and_then(|w| w.parent) {
+            id = parent;
+        }
+        id
+    }
+
+    pub fn bring_to_front(&mut self, id: WidgetId) {
+        let root = self.root_of(id);
+        if let Some(i) = self.roots.iter().position(|r| *r == root) {
+            self.roots.remove(i);
+            self.roots.push(root);
+        }
+    }
+
+    pub fn bounds(&self, id: WidgetId) -> Rect {
+        self.widgets.get(&id).map_or(Rect::default(), |w| w.bounds)
+    }
+
+    /// 标签, 按钮, 复选框的文字或输入框的内容
+    pub fn text(&self, id: WidgetId) -> &str {
+        match self.widgets.get(&id).map(|w| &w.kind) {
+            Some(WidgetKind::Label { text }) | Some(WidgetKind::Input { text, .. }) => text,
+            Some(WidgetKind::Button { label, .. }) | Some(WidgetKind::CheckBox { label, .. }) => label,
+            Some(WidgetKind::Window { title, .. }) => title,
+            _ => "",
+        }
+    }
+
+    pub fn set_text(&mut self, id: WidgetId, value: &str) {
+        match self.widgets.get_mut(&id).map(|w| &mut w.kind) {
+            Some(WidgetKind::Label { text }) | Some(WidgetKind::Input { text, .. }) => *text = String::from(value),
+            Some(WidgetKind::Button { label, .. }) | Some(WidgetKind::CheckBox { label, .. }) => *label = String::from(value),
+            Some(WidgetKind::Window { title, .. }) => *title = String::from(value),
+            _ => {}
+        }
+    }
+
+    pub fn set_checked(&mut self, id: WidgetId, value: bool) {
+        if let Some(WidgetKind::CheckBox { checked, .. }) = self.widgets.get_mut(&id).map(|w| &mut w.kind) {
+            *checked = value;
+        }
+    }
+
+    pub fn selected(&self, id: WidgetId) -> Option<usize> {
+        match self.widgets.get(&id).map(|w| &w.kind) {
+            Some(WidgetKind::List { selected, .. }) => *selected,
+            _ => None,
+        }
+    }
+
+    pub fn set_selected(&mut self, id: WidgetId, value: Option<usize>) {
+        if let Some(WidgetKind::List { items, selected, .. }) = self.widgets.get_mut(&id).map(|w| &mut w.kind) {
+            *selected = value.filter(|v| *v < items.len());
+        }
+    }
+
+    pub fn set_items(&mut self, id: WidgetId, values: Vec<String>) {
+        if let Some(WidgetKind::List { items, selected, scroll, .. }) = self.widgets.get_mut(&id).map(|w| &mut w.kind) {
+            *items = values;
+            *selected = selected.filter(|s| *s < items.len());
+            *scroll = 0;
+        }
+        self.sync_scrollbar(id);
+    }
+
+    /// 列表滚动时同步滚动条, 拖动滚动条时同步列表
+    pub fn bind_scrollbar(&mut self, list: WidgetId, bar: WidgetId) {
+        if let Some(WidgetKind::List { scrollbar, .. }) = self.widgets.get_mut(&list).map(|w| &mut w.kind) {
+            *scrollbar = Some(bar);
+        }
+        self.sync_scrollbar(list);
+    }
+
+    pub fn scroll_value(&self, id: WidgetId) -> f32 {
+        match self.widgets.get(&id).map(|w| &w.kind) {
+            Some(WidgetKind::ScrollBar { value, .. }) => *value,
+            _ => 0.,
+        }
+    }
+
+    pub fn focus(&mut self, id: WidgetId) {
+        self.focus = Some(id);
+    }
+
+    /// 列表可见行数和最大滚动行数
+    fn list_rows(widget: &Widget) -> (usize, usize) {
+        match &widget.kind {
+            WidgetKind::List { items, row_height, .. } => {
+                let rows = (widget.rect.h / row_height.max(1.)).floor().max(1.) as usize;
+                (rows, items.len().saturating_sub(rows))
+            }
+            _ => (0, 0),
+        }
+    }
+
+    fn sync_scrollbar(&mut self, list: WidgetId) {
+        let (rows, max_scroll, scroll, bar, total) = match self.widgets.get(&list) {
+            Some(w) => match &w.kind {
+                WidgetKind::List { scroll, scrollbar: Some(bar), items, .. } => {
+                    let (rows, max_scroll) = Self::list_rows(w);
+                    (rows, max_scroll, *scroll, *bar, items.len())
+                }
+                _ => return,
+            },
+            None => return,
+        };
+        if let Some(WidgetKind::ScrollBar { value, page }) = self.widgets.get_mut(&bar).map(|w| &mut w.kind) {
+            *value = if max_scroll == 0 { 0. } else { scroll as f32 / max_scroll as f32 };
+            *page = if total == 0 { 1. } else { (rows as f32 / total as f32).min(1.) };
+        }
+    }
+
+    fn scroll_list(&mut self, list: WidgetId, scroll_to: usize) {
+        if let Some(w) = self.widgets.get_mut(&list) {
+            let (_, max_scroll) = Self::list_rows(w);
+            if let WidgetKind::List { scroll, .. } = &mut w.kind {
+                *scroll = scroll_to.min(max_scroll);
+            }
+        }
+        self.sync_scrollbar(list);
+    }
+
+    /// 计算所有控件的屏幕坐标
+    pub fn layout(&mut self) {
+        let window = Rect::new(0., 0., self.window_size.0, self.window_size.1);
+        for root in self.roots.clone() {
+            self.layout_widget(root, window);
+        }
+    }
+
+    fn layout_widget(&mut self, id: WidgetId, parent: Rect) {
+        let (children, layout, bounds) = match self.widgets.get_mut(&id) {
+            Some(w) => {
+                let (x, y) = w.anchor.origin(parent, w.rect.w, w.rect.h);
+                w.bounds = Rect::new(x + w.rect.x, y + w.rect.y, w.rect.w, w.rect.h);
+                (w.children.clone(), w.layout, w.bounds)
+            }
+            None => return,
+        };
+        let mut cursor = 0.;
+        for child in children {
+            let (visible, size) = match self.widgets.get(&child) {
+                Some(c) => (c.visible, (c.rect.w, c.rect.h)),
+                None => continue,
+            };
+            match layout {
+                Layout::Absolute => self.layout_widget(child, bounds),
+                Layout::Horizontal { padding, spacing } => {
+                    let slot = Rect::new(bounds.x + padding + cursor, bounds.y + padding, size.0, bounds.h - padding * 2.);
+                    self.layout_at(child, slot);
+                    if visible {
+                        cursor += size.0 + spacing;
+                    }
+                }
+            }
+        }
+    }
+
+    /// 顺序排列: 左上角固定在 slot, 再加上控件自身的偏移
+    fn layout_at(&mut self, id: WidgetId, slot: Rect) {
+        let anchor = match self.widgets.get_mut(&id) {
+            Some(w) => std::mem::replace(&mut w.anchor, Anchor::TopLeft),
+            None => return,
+        };
+        self.layout_widget(id, slot);
+        if let Some(w) = self.widgets.get_mut(&id) {
+            w.anchor = anchor;
+        }
+    }
+
+    /// 鼠标下最上层的可见控件
+    pub fn hit(&self, x: f32, y: f32) -> Option<WidgetId> {
+        self.roots.iter().rev().find_map(|root| self.hit_widget(*root, x, y))
+    }
+
+    fn hit_widget(&self, id: WidgetId, x: f32, y: f32) -> Option<WidgetId> {
+        let w = self.widgets.get(&id)?;
+        if !w.visible {
+            return None;
+        }
+        if let Some(child) = w.children.iter().rev().find_map(|c| self.hit_widget(*c, x, y)) {
+            return Some(child);
+        }
+        if w.bounds.contains(vec2(x, y)) {
+            return Some(id);
+        }
+        None
+    }
+
+    /// 返回是否被界面处理, 未处理的输入交给场景
+    pub fn mouse_button_down(&mut self, button: MouseButton, x: f32, y: f32) -> bool {
+        self.layout();
+        self.mouse = (x, y);
+        let id = match self.hit(x, y) {
+            Some(id) => id,
+            None => {
+                self.focus = None;
+                return false;
+            }
+        };
+        self.bring_to_front(id);
+        if button != MouseButton::Left {
+            return true;
+        }
+        self.pressed = Some(id);
+        let bounds = self.bounds(id);
+        match self.widgets.get(&id).map(|w| (&w.kind, w.draggable)) {
+            Some((WidgetKind::Input { .. }, _)) => self.focus = Some(id),
+            Some((WidgetKind::ScrollBar { .. }, _)) => self.drag_scrollbar(id, y),
+            Some((WidgetKind::List { row_height, scroll, items, .. }, _)) => {
+                let row = scroll + ((y - bounds.y) / row_height.max(1.)) as usize;
+                if row < items.len() {
+                    if let Some(WidgetKind::List { selected, .. }) = self.widgets.get_mut(&id).map(|w| &mut w.kind) {
+                        *selected = Some(row);
+                    }
+                    self.events.push_back(UiEvent::Selected(id, row));
+                }
+            }
+            Some((WidgetKind::Window { .. }, true)) => self.drag = Some((id, x - bounds.x, y - bounds.y)),
+            _ => {}
+        }
+        true
+    }
+
+    pub fn mouse_button_up(&mut self, button: MouseButton, x: f32, y: f32) -> bool {
+        self.layout();
+        self.mouse = (x, y);
+        self.drag = None;
+        let pressed = match self.pressed.take() {
+            Some(pressed) if button == MouseButton::Left => pressed,
+            _ => return self.hit(x, y).is_some(),
+        };
+        if self.hit(x, y) != Some(pressed) {
+            return true;
+        }
+        match self.widgets.get_mut(&pressed).map(|w| &mut w.kind) {
+            Some(WidgetKind::Button { .. }) => self.events.push_back(UiEvent::Clicked(pressed)),
+            Some(WidgetKind::CheckBox { checked, .. }) => {
+                *checked = !*checked;
+                let checked = *checked;
+                self.events.push_back(UiEvent::Toggled(pressed, checked));
+            }
+            _ => {}
+        }
+        true
+    }
+
+    pub fn mouse_motion(&mut self, x: f32, y: f32) -> bool {
+        self.layout();
+        self.mouse = (x, y);
+        if let Some((id, dx, dy)) = self.drag {
+            let bounds = self.bounds(id);
+            if let Some(w) = self.widgets.get_mut(&id) {
+                w.rect.x += x - dx - bounds.x;
+                w.rect.y += y - dy - bounds.y;
+            }
+            return true;
+        }
+        if let Some(id) = self.pressed {
+            if let Some(WidgetKind::ScrollBar { .. }) = self.widgets.get(&id).map(|w| &w.kind) {
+                self.drag_scrollbar(id, y);
+                return true;
+            }
+        }
+        self.hover = self.hit(x, y);
+        self.hover.is_some()
+    }
+
+    pub fn mouse_wheel(&mut self, _x: f32, y: f32) -> bool {
+        self.layout();
+        let id = match self.hit(self.mouse.0, self.mouse.1) {
+            Some(id) => id,
+            None => return false,
+        };
+        // 滚轮作用在鼠标下的列表, 或与滚动条绑定的列表
+        let list = self.widgets.iter().find(|(list, w)| match &w.kind {
+            WidgetKind::List { scrollbar, .. } => **list == id || *scrollbar == Some(id),
+            _ => false,
+        }).map(|(list, w)| (*list, match &w.kind {
+            WidgetKind::List { scroll, .. } => *scroll,
+            _ => 0,
+        }));
+        if let Some((list, scroll)) = list {
+            let scroll = if y > 0. { scroll.saturating_sub(1) } else { scroll + 1 };
+            self.scroll_list(list, scroll);
+        }
+        true
+    }
+
+    fn drag_scrollbar(&mut self, id: WidgetId, y: f32) {
+        let bounds = self.bounds(id);
+        if let Some(WidgetKind::ScrollBar { value, page }) = self.widgets.get_mut(&id).map(|w| &mut w.kind) {
+            let thumb = (bounds.h * *page).max(SCROLL_MIN_THUMB).min(bounds.h);
+            let track = (bounds.h - thumb).max(1.);
+            *value = ((y - bounds.y - thumb / 2.) / track).clamp(0., 1.);
+        }
+        self.events.push_back(UiEvent::Changed(id));
+        let value = self.scroll_value(id);
+        let list = self.widgets.iter().find(|(_, w)| matches!(w.kind, WidgetKind::List { scrollbar: Some(bar), .. } if bar == id)).map(|(l, _)| *l);
+        if let Some(list) = list {
+            let max_scroll = self.widgets.get(&list).map_or(0, |w| Self::list_rows(w).1);
+            self.scroll_list(list, (value * max_scroll as f32).round() as usize);
+        }
+    }
+
+    /// 焦点所在窗口中的输入框, 按树的顺序
+    fn inputs_in(&self, root: WidgetId, result: &mut Vec<WidgetId>) {
+        if let Some(w) = self.widgets.get(&root) {
+            if !w.visible {
+                return;
+            }
+            if let WidgetKind::Input { .. } = w.kind {
+                result.push(root);
+            }
+            for child in w.children.iter() {
+                self.inputs_in(*child, result);
+            }
+        }
+    }
+
+    pub fn key_down(&mut self, input: KeyInput) -> bool {
+        let focus = match self.focus {
+            Some(focus) => focus,
+            None => return false,
+        };
+        match input.keycode {
+            Some(KeyCode::Back) => {
+                if let Some(WidgetKind::Input { text, .. }) = self.widgets.get_mut(&focus).map(|w| &mut w.kind) {
+                    text.pop();
+                    self.events.push_back(UiEvent::Changed(focus));
+                }
+            }
+            Some(KeyCode::Tab) => {
+                let mut inputs = Vec::new();
+                self.inputs_in(self.root_of(focus), &mut inputs);
+                if let Some(i) = inputs.iter().position(|i| *i == focus) {
+                    self.focus = Some(inputs[(i + 1) % inputs.len()]);
+                }
+            }
+            Some(KeyCode::Return) | Some(KeyCode::NumpadEnter) => self.events.push_back(UiEvent::Submitted(focus)),
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn text_input(&mut self, character: char) -> bool {
+        let focus = match self.focus {
+            Some(focus) => focus,
+            None => return false,
+        };
+        if character.is_control() {
+            return true;
+        }
+        if let Some(WidgetKind::Input { text, max_len, .. }) = self.widgets.get_mut(&focus).map(|w| &mut w.kind) {
+            if text.chars().count() < *max_len {
+                text.push(character);
+                self.events.push_back(UiEvent::Changed(focus));
+            }
+        }
+        true
+    }
+
+    pub fn draw(&mut self, ctx: &mut Context, canvas: &mut Canvas, cache: &mut ImageCache, text: &mut TextRenderer) -> GameResult {
+        self.layout();
+        for root in self.roots.clone() {
+            self.draw_widget(ctx, canvas, cache, text, root)?;
+        }
+        Ok(())
+    }
+
+    fn draw_widget(&self, ctx: &mut Context, canvas: &mut Canvas, cache: &mut ImageCache, text: &mut TextRenderer, id: WidgetId) -> GameResult {
+        let w = match self.widgets.get(&id) {
+            Some(w) if w.visible => w,
+            _ => return Ok(()),
+        };
+        let b = w.bounds;
+        let text_color = w.color.unwrap_or(Color::WHITE);
+        let hover = self.hover == Some(id);
+        let pressed = hover && self.pressed == Some(id);
+        match &w.kind {
+            WidgetKind::Window { frame, title } => {
+                if !frame.is_some_and(|key| frame::draw_frame(ctx, canvas, cache, key, b.x, b.y, Color::WHITE)) {
+                    fill(canvas, b, w.color.unwrap_or(PANEL_COLOR));
+                }
+                if !title.is_empty() {
+                    text.draw_centered(ctx, canvas, title, Rect::new(b.x, b.y + 6., b.w, w.text_size), TextStyle::new(w.text_size, Color::WHITE).shadow());
+                }
+            }
+            WidgetKind::Button { pressed: frame_key, label } => {
+                // 按下的图片自带文字, 没有图片时才画底色和文字
+                if !(pressed && frame_key.is_some_and(|key| frame::draw_frame(ctx, canvas, cache, key, b.x, b.y, Color::WHITE))) {
+                    let color = if pressed { PRESSED_COLOR } else if hover { HOVER_COLOR } else { BUTTON_COLOR };
+                    fill(canvas, b, w.color.map_or(color, |c| if hover { HOVER_COLOR } else { c }));
+                    if !label.is_empty() {
+                        text.draw_centered(ctx, canvas, label, b, TextStyle::new(w.text_size, text_color));
+                    }
+                }
+            }
+            WidgetKind::Label { text: value } => {
+                text.draw_wrapped(ctx, canvas, value, b, TextStyle::new(w.text_size, text_color));
+            }
+            WidgetKind::Input { text: value, password, .. } => {
+                fill(canvas, b, INPUT_COLOR);
+                let focused = self.focus == Some(id);
+                if focused {
+                    canvas.draw(&Mesh::new_rectangle(ctx, DrawMode::stroke(1.), b, FOCUS_COLOR)?, DrawParam::default());
+                }
+                let shown = if *password { "*".repeat(value.chars().count()) } else { value.clone() };
+                let caret = if focused && (ctx.time.time_since_start().as_millis() / 500).is_multiple_of(2) { "_" } else { "" };
+                let block = text.layout_plain(ctx, &format!("{}{}", shown, caret), w.text_size, text_color);
+                text.draw_block(canvas, &block, b.x + 4., b.y + (b.h - block.height) / 2., TextEffect::None);
+            }
+            WidgetKind::ScrollBar { value, page } => {
+                fill(canvas, b, INPUT_COLOR);
+                let thumb = (b.h * page).max(SCROLL_MIN_THUMB).min(b.h);
+                fill(canvas, Rect::new(b.x + 1., b.y + (b.h - thumb) * value, b.w - 2., thumb), if pressed || self.pressed == Some(id) { HOVER_COLOR } else { BUTTON_COLOR });
+            }
+            WidgetKind::List { items, selected, scroll, row_height, .. } => {
+                fill(canvas, b, INPUT_COLOR);
+                let (rows, _) = Self::list_rows(w);
+                for (i, item) in items.iter().enumerate().skip(*scroll).take(rows) {
+                    let row = Rect::new(b.x, b.y + (i - scroll) as f32 * row_height, b.w, *row_height);
+                    if *selected == Some(i) {
+                        fill(canvas, row, SELECTED_COLOR);
+                    }
+                    let block = text.layout(ctx, item, w.text_size, text_color, None);
+                    text.draw_block(canvas, &block, row.x + 4., row.y + (row_height - block.height) / 2., TextEffect::None);
+                }
+            }
+            WidgetKind::CheckBox { checked, label } => {
+                let size = b.h.min(14.);
+                let check = Rect::new(b.x, b.y + (b.h - size) / 2., size, size);
+                fill(canvas, check, INPUT_COLOR);
+                canvas.draw(&Mesh::new_rectangle(ctx, DrawMode::stroke(1.), check, if hover { FOCUS_COLOR } else { text_color })?, DrawParam::default());
+                if *checked {
+                    fill(canvas, Rect::new(check.x + 3., check.y + 3., size - 6., size - 6.), FOCUS_COLOR);
+                }
+                let block = text.layout(ctx, label, w.text_size, text_color, None);
+                text.draw_block(canvas, &block, b.x + size + 6., b.y + (b.h - block.height) / 2., TextEffect::None);
+            }
+        }
+        for child in w.children.iter() {
+            self.draw_widget(ctx, canvas, cache, text, *child)?;
+        }
+        Ok(())
+    }
+}
+
+fn fill(canvas: &mut Canvas, rect: Rect, color: Color) {
+    canvas.draw(&Quad, DrawParam::default().dest(rect.point()).scale(rect.size()).color(color));
+}
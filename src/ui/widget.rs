@@ -0,0 +1,166 @@
+use ggez::graphics::{Color, Rect};
+use crate::cache::CacheKey;
+
+pub type WidgetId = usize;
+
+/// 控件相对父控件(顶层控件相对窗口)的对齐方式, rect 的 x/y 为对齐后的偏移
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Anchor {
+    #[default]
+    TopLeft,
+    Center,
+}
+
+impl Anchor {
+    /// 在 parent 中放置 width x height 的控件时左上角的位置
+    pub fn origin(&self, parent: Rect, width: f32, height: f32) -> (f32, f32) {
+        match self {
+            Anchor::TopLeft => (parent.x, parent.y),
+            Anchor::Center => (parent.x + (parent.w - width) / 2., parent.y + (parent.h - height) / 2.),
+        }
+    }
+}
+
+/// 子控件的排列方式, 顺序排列时忽略子控件的 anchor
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Layout {
+    #[default]
+    Absolute,
+    Horizontal { padding: f32, spacing: f32 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WidgetKind {
+    Window { frame: Option<CacheKey>, title: String },
+    /// pressed 为按下时的图片, 平时按钮画在窗口背景里
+    Button { pressed: Option<CacheKey>, label: String },
+    Label { text: String },
+    Input { text: String, password: bool, max_len: usize },
+    /// 竖直滚动条, value 为 0~1, page 为可见部分占总长的比例
+    ScrollBar { value: f32, page: f32 },
+    /// scroll 为第一行的序号, scrollbar 为联动的滚动条
+    List { items: Vec<String>, selected: Option<usize>, scroll: usize, row_height: f32, scrollbar: Option<WidgetId> },
+    CheckBox { checked: bool, label: String },
+}
+
+pub struct Widget {
+    pub(crate) kind: WidgetKind,
+    pub(crate) rect: Rect,
+    pub(crate) anchor: Anchor,
+    pub(crate) layout: Layout,
+    pub(crate) color: Option<Color>,
+    pub(crate) text_size: f32,
+    pub(crate) visible: bool,
+    pub(crate) draggable: bool,
+    pub(crate) parent: Option<WidgetId>,
+    pub(crate) children: Vec<WidgetId>,
+    /// 布局后的屏幕坐标
+    pub(crate) bounds: Rect,
+}
+
+impl Widget {
+    pub fn new(kind: WidgetKind, rect: Rect) -> Self {
+        Self {
+            kind,
+            rect,
+            anchor: Anchor::TopLeft,
+            layout: Layout::Absolute,
+            color: None,
+            text_size: 14.,
+            visible: true,
+            draggable: false,
+            parent: None,
+            children: Vec::new(),
+            bounds: rect,
+        }
+    }
+
+    pub fn window(width: f32, height: f32) -> Self {
+        Self::new(WidgetKind::Window { frame: None, title: String::new() }, Rect::new(0., 0., width, height))
+    }
+
+    pub fn button(rect: Rect, label: &str) -> Self {
+        Self::new(WidgetKind::Button { pressed: None, label: String::from(label) }, rect)
+    }
+
+    pub fn label(rect: Rect, text: &str) -> Self {
+        Self::new(WidgetKind::Label { text: String::from(text) }, rect)
+    }
+
+    pub fn input(rect: Rect, max_len: usize) -> Self {
+        Self::new(WidgetKind::Input { text: String::new(), password: false, max_len }, rect)
+    }
+
+    pub fn scrollbar(rect: Rect) -> Self {
+        Self::new(WidgetKind::ScrollBar { value: 0., page: 1. }, rect)
+    }
+
+    pub fn list(rect: Rect, row_height: f32) -> Self {
+        Self::new(WidgetKind::List { items: Vec::new(), selected: None, scroll: 0, row_height, scrollbar: None }, rect)
+    }
+
+    pub fn checkbox(rect: Rect, label: &str, checked: bool) -> Self {
+        Self::new(WidgetKind::CheckBox { checked, label: String::from(label) }, rect)
+    }
+
+    pub fn anchor(mut self, anchor: Anchor) -> Self {
+        self.anchor = anchor;
+        self
+    }
+
+    pub fn at(mut self, x: f32, y: f32) -> Self {
+        self.rect.x = x;
+        self.rect.y = y;
+        self
+    }
+
+    pub fn layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// 文字颜色, 没有图片的窗口和按钮为背景色
+    pub fn color(mut self, color: Color) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    pub fn hidden(mut self) -> Self {
+        self.visible = false;
+        self
+    }
+
+    pub fn draggable(mut self) -> Self {
+        self.draggable = true;
+        self
+    }
+
+    /// 窗口的背景图片
+    pub fn frame(mut self, key: CacheKey) -> Self {
+        if let WidgetKind::Window { frame, .. } = &mut self.kind {
+            *frame = Some(key);
+        }
+        self
+    }
+
+    pub fn title(mut self, text: &str) -> Self {
+        if let WidgetKind::Window { title, .. } = &mut self.kind {
+            *title = String::from(text);
+        }
+        self
+    }
+
+    pub fn pressed_frame(mut self, key: CacheKey) -> Self {
+        if let WidgetKind::Button { pressed, .. } = &mut self.kind {
+            *pressed = Some(key);
+        }
+        self
+    }
+
+    pub fn password(mut self) -> Self {
+        if let WidgetKind::Input { password, .. } = &mut self.kind {
+            *password = true;
+        }
+        self
+    }
+}